@@ -0,0 +1,25 @@
+//! The timing loop shared by the benches.
+
+use std::time::{Duration, Instant};
+
+const SAMPLES: usize = 5;
+
+/// Runs `f` once to warm up, then `SAMPLES` times, and reports the median
+/// time and how many `unit`s a second that is, given `f` handles `count`.
+pub fn bench<F: FnMut()>(name: &str, count: usize, unit: &str, mut f: F) {
+  f();
+
+  let mut times: Vec<Duration> = (0..SAMPLES).map(|_| {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+  }).collect();
+  times.sort();
+
+  let median = times[SAMPLES / 2];
+  let secs = median.as_secs() as f64 + f64::from(median.subsec_nanos()) / 1e9;
+  let rate = count as f64 / secs;
+  let (rate, prefix) = if rate >= 1e6 { (rate / 1e6, "M") } else { (rate / 1e3, "k") };
+  println!("{:<24} {:>10.3} ms  {:>8.1} {}{}/s  (min {:?}, max {:?})",
+    name, secs * 1e3, rate, prefix, unit, times[0], times[SAMPLES - 1]);
+}
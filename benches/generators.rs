@@ -0,0 +1,47 @@
+//! Throughput of the day 15 generators. Run with `cargo bench --bench generators`.
+
+extern crate adventofcode;
+
+mod common;
+
+use common::bench;
+use adventofcode::fifteen::{Generator, count_matching_pairs, count_matching_pairs_batched, count_matching_pairs_parallel};
+
+/// The generator as it was before the Mersenne reduction, dividing to take
+/// each value modulo 2^31 - 1.
+struct DividingGenerator {
+  seed: usize,
+  factor: usize,
+}
+
+impl Iterator for DividingGenerator {
+  type Item = usize;
+  fn next(&mut self) -> Option<usize> {
+    self.seed = self.seed * self.factor % 2147483647;
+    Some(self.seed)
+  }
+}
+
+fn main() {
+  let sample = 40_000_000;
+  let gen_a = Generator::new(65, 16807);
+  let gen_b = Generator::new(8921, 48271);
+
+  bench("dividing iterator chain", 2 * sample, "values", || {
+    let div_a = DividingGenerator { seed: 65, factor: 16807 };
+    let div_b = DividingGenerator { seed: 8921, factor: 48271 };
+    assert_eq!(588, count_matching_pairs(div_a, div_b, sample));
+  });
+
+  bench("iterator chain", 2 * sample, "values", || {
+    assert_eq!(588, count_matching_pairs(gen_a.clone(), gen_b.clone(), sample));
+  });
+
+  bench("batched", 2 * sample, "values", || {
+    assert_eq!(588, count_matching_pairs_batched(gen_a.clone(), gen_b.clone(), sample));
+  });
+
+  bench("batched, parallel", 2 * sample, "values", || {
+    assert_eq!(588, count_matching_pairs_parallel(gen_a.clone(), gen_b.clone(), sample));
+  });
+}
@@ -0,0 +1,95 @@
+//! Throughput of the day 10 knot hash, alone and building the day 14 disk.
+//! Run with `cargo bench --bench knot_hash`.
+
+extern crate adventofcode;
+
+mod common;
+
+use common::bench;
+use adventofcode::fourteen::create_disk_for_keystring;
+use adventofcode::ten::{KnotHasher, knot_hash_batch};
+
+/// The knot hash as it was before rounds reversed in place, collecting each
+/// reversed segment into a new Vec and copying it back.
+mod allocating {
+  fn reverse_slice_segment(slice: &[u8], from: usize, length: usize) -> Vec<u8> {
+    let mut cycle: Vec<u8> = slice.iter().cycle().skip(from).take(length).cloned().collect();
+    cycle.reverse();
+    cycle
+  }
+
+  fn hash_slice(lengths: &[usize], iterations: usize) -> Vec<u8> {
+    let mut input_vec: Vec<u8> = (0..=255u8).collect();
+    let input_len = input_vec.len();
+    let mut pos = 0;
+    let mut skip = 0;
+
+    for _ in 0..iterations {
+      for length in lengths {
+        let reversed = reverse_slice_segment(&input_vec, pos, *length);
+        let mut i = pos;
+        for n in reversed {
+          input_vec[i] = n;
+          i = (i + 1) % input_len;
+        }
+
+        pos = (pos + length + skip) % input_len;
+        skip += 1;
+      }
+    }
+
+    input_vec
+  }
+
+  pub fn knot_hash(input: &str) -> Vec<u8> {
+    let mut lengths: Vec<usize> = input.bytes().map(|byte| byte as usize).collect();
+    lengths.extend_from_slice(&[17, 31, 73, 47, 23]);
+    hash_slice(&lengths, 64).chunks(16)
+      .map(|block| block.iter().fold(0, |h, i| h ^ i))
+      .collect()
+  }
+
+  /// The used squares of the day 14 disk, hashing each row in turn.
+  pub fn used_squares(keystring: &str) -> usize {
+    (0..128)
+      .map(|row| knot_hash(&format!("{}-{}", keystring, row)).iter().map(|byte| byte.count_ones() as usize).sum::<usize>())
+      .sum()
+  }
+}
+
+fn used_squares(keystring: &str) -> usize {
+  let disk = create_disk_for_keystring(keystring.to_string());
+  (0..disk.rows())
+    .map(|row| (0..disk.cols()).filter(|&col| disk.is_used(row, col)).count())
+    .sum()
+}
+
+fn main() {
+  let keys: Vec<String> = (0..1024).map(|row| format!("flqrgnkx-{}", row)).collect();
+
+  bench("allocating reverse", keys.len(), "hashes", || {
+    for key in &keys {
+      assert!(allocating::knot_hash(key).len() == 16);
+    }
+  });
+
+  bench("KnotHasher", keys.len(), "hashes", || {
+    for key in &keys {
+      let mut hasher = KnotHasher::new();
+      hasher.update(key.as_bytes());
+      assert!(hasher.finalize().len() == 16);
+    }
+  });
+
+  bench("knot_hash_batch", keys.len(), "hashes", || {
+    assert_eq!(keys.len(), knot_hash_batch(&keys).len());
+  });
+
+  bench("day 14 disk, allocating", 128, "hashes", || {
+    assert_eq!(8108, allocating::used_squares("flqrgnkx"));
+  });
+
+  bench("day 14 disk", 128, "hashes", || {
+    assert_eq!(8108, used_squares("flqrgnkx"));
+  });
+}
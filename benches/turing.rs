@@ -0,0 +1,35 @@
+//! Throughput of the day 25 Turing machine. Run with `cargo bench --bench turing`.
+
+extern crate adventofcode;
+
+mod common;
+
+use common::bench;
+use adventofcode::twentyfive::{Machine, MacroMachine, TuringMachine};
+
+fn main() {
+  let input = include_str!("../input/25");
+
+  let puzzle_steps = TuringMachine::from(input).max_steps();
+  bench("puzzle input", puzzle_steps, "steps", || {
+    let mut machine = TuringMachine::from(input);
+    machine.run_to_max_steps();
+    assert!(machine.checksum() > 0);
+  });
+
+  let steps = 1_000_000_000;
+  bench("puzzle input, 10^9", steps, "steps", || {
+    let mut machine = TuringMachine::from(input);
+    machine.run(steps);
+    assert!(machine.checksum() > 0);
+  });
+
+  for &k in &[8, 16, 32] {
+    bench(&format!("macro machine, k = {}", k), steps, "steps", || {
+      let machine = TuringMachine::from(input);
+      let mut macro_machine = MacroMachine::new(&machine, k);
+      macro_machine.run_steps(steps as u128);
+      assert!(macro_machine.checksum() > 0);
+    });
+  }
+}
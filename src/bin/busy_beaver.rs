@@ -0,0 +1,30 @@
+//! Enumerates every n-state, 2-symbol Turing machine and reports the busy
+//! beaver champions. Usage: `busy_beaver [states] [step budget]`.
+
+extern crate adventofcode;
+
+use std::env;
+use std::time::Instant;
+use adventofcode::twentyfive::enumerate_busy_beavers;
+
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+  let states = args.first().map_or(3, |arg| arg.parse().expect("state count must be a number"));
+  let budget = args.get(1).map_or(1000, |arg| arg.parse().expect("step budget must be a number"));
+
+  let start = Instant::now();
+  let report = enumerate_busy_beavers(states, budget);
+  let elapsed = start.elapsed();
+
+  println!("{} states, {} step budget", states, budget);
+  println!("Machines run:      {}", report.machines);
+  println!("Halted:            {}", report.halted);
+  println!("Hit step budget:   {}", report.step_limited);
+  if let Some((steps, ref machine)) = report.most_steps {
+    println!("Most steps:        {} ({})", steps, machine);
+  }
+  if let Some((ones, ref machine)) = report.most_ones {
+    println!("Most ones:         {} ({})", ones, machine);
+  }
+  println!("Elapsed:           {:?}", elapsed);
+}
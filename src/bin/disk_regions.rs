@@ -0,0 +1,58 @@
+//! Reports on the regions of day 14 disks, to compare how fragmented they
+//! are. Usage: `disk_regions [--diagonal] [--wrap] [--rows N] [--ring-size N]
+//! [--block-size N] [--ascii] [--ppm DIR] KEYSTRING...`. Each row is a knot
+//! hash with the given ring and block size, so a disk has 8 columns per block
+//! of the ring. With `--ppm`, each disk's region map is written to
+//! `DIR/KEYSTRING.ppm`.
+
+extern crate adventofcode;
+
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use adventofcode::fourteen::{Adjacency, create_disk, label_regions};
+use adventofcode::ten::KnotHashParams;
+
+fn main() {
+  let mut args = env::args().skip(1);
+  let mut adjacency = Adjacency::default();
+  let mut rows = 128;
+  let mut params = KnotHashParams::new();
+  let mut ascii = false;
+  let mut ppm_dir = None;
+  let mut keystrings = vec![];
+
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--diagonal" => adjacency.diagonal = true,
+      "--wrap" => adjacency.wrap = true,
+      "--ascii" => ascii = true,
+      "--rows" => rows = args.next().and_then(|n| n.parse().ok()).expect("--rows needs a number"),
+      "--ring-size" => params = params.ring_size(args.next().and_then(|n| n.parse().ok()).expect("--ring-size needs a number")),
+      "--block-size" => params = params.block_size(args.next().and_then(|n| n.parse().ok()).expect("--block-size needs a number")),
+      "--ppm" => ppm_dir = Some(args.next().expect("--ppm needs a directory")),
+      _ => keystrings.push(arg),
+    }
+  }
+  if keystrings.is_empty() {
+    panic!("usage: disk_regions [--diagonal] [--wrap] [--rows N] [--ring-size N] [--block-size N] [--ascii] [--ppm DIR] KEYSTRING...");
+  }
+
+  for keystring in &keystrings {
+    let map = label_regions(&create_disk(keystring, rows, &params), adjacency);
+
+    println!("== {} ==", keystring);
+    print!("{}", map.stats());
+    if ascii {
+      print!("{}", map.to_ascii());
+    }
+    if let Some(ref dir) = ppm_dir {
+      let path = Path::new(dir).join(format!("{}.ppm", keystring));
+      let mut out = BufWriter::new(File::create(&path).expect("could not create image"));
+      map.write_ppm(&mut out, 4).expect("could not write image");
+      println!("Wrote {}", path.display());
+    }
+    println!();
+  }
+}
@@ -0,0 +1,67 @@
+//! Searches for collisions and preimages on the first bits of the knot hash,
+//! and compares the work it took with what an ideal hash would need. Usage:
+//! `knot_search collision|preimage <bits> [trials=10]`.
+
+extern crate adventofcode;
+
+use std::env;
+use std::time::Instant;
+use adventofcode::ten::{expected_collision_work, expected_preimage_work, find_collision, find_preimage, hash_prefix, knot_hash_batch, to_hex};
+
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+  let usage = "usage: knot_search collision|preimage <bits> [trials]";
+  let mode = args.first().expect(usage);
+  let bits: u32 = args.get(1).expect(usage).parse().expect("bits must be a number");
+  let trials: u64 = args.get(2).map_or(10, |arg| arg.parse().expect("trials must be a number"));
+
+  let expected = match mode.as_str() {
+    "collision" => expected_collision_work(bits),
+    "preimage" => expected_preimage_work(bits),
+    _ => panic!("{}", usage),
+  };
+  // far past the expected work, so a trial only gives up if something's wrong
+  let max_hashes = (expected * 64.0) as u64;
+
+  let start = Instant::now();
+  // trials that give up stopped at the limit, not at a result, so they're
+  // left out of the mean
+  let (mut total, mut succeeded, mut failed) = (0, 0, 0);
+  for trial in 0..trials {
+    let search = if mode == "collision" {
+      find_collision(bits, &format!("collision-{}-", trial), max_hashes)
+    }
+    else {
+      let target_input = format!("target-{}", trial);
+      let target = hash_prefix(&knot_hash_batch(&[&target_input])[0], bits);
+      find_preimage(target, bits, &format!("preimage-{}-", trial), max_hashes)
+    };
+    if search.found.is_empty() {
+      failed += 1;
+      println!("Trial {:>3}: gave up after {} hashes", trial, search.hashes);
+      continue;
+    }
+    total += search.hashes;
+    succeeded += 1;
+
+    let hashes = knot_hash_batch(&search.found);
+    let found: Vec<String> = search.found.iter().zip(&hashes)
+      .map(|(input, hash)| format!("{:?} -> {}", input, to_hex(hash)))
+      .collect();
+    println!("Trial {:>3}: {:>12} hashes  {}", trial, search.hashes, found.join(", "));
+  }
+
+  println!("{} on the first {} bits, {} trials", mode, bits, trials);
+  println!("Expected work:     {:.0} hashes", expected);
+  if succeeded > 0 {
+    let mean = total as f64 / succeeded as f64;
+    println!("Mean observed:     {:.0} hashes ({:.2}x expected, over {} successful trials)", mean, mean / expected, succeeded);
+  }
+  else {
+    println!("Mean observed:     none, no trial succeeded");
+  }
+  if failed > 0 {
+    println!("Failed trials:     {} gave up after {} hashes", failed, max_hashes);
+  }
+  println!("Elapsed:           {:?}", start.elapsed());
+}
@@ -0,0 +1,190 @@
+//! Prints or checks knot hashes of files, like `sha256sum`. Usage:
+//! `knotsum [FILE]...` prints `<hash>  <filename>` for each file, reading
+//! stdin when there are none or a file is `-`. `knotsum -c [FILE]...` reads
+//! lines in that format back and checks each file still has the same hash.
+
+extern crate adventofcode;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::process;
+use adventofcode::ten::{KnotHasher, to_hex};
+
+fn open(name: &str) -> io::Result<Box<dyn Read>> {
+  if name == "-" {
+    Ok(Box::new(io::stdin()))
+  }
+  else {
+    Ok(Box::new(File::open(name)?))
+  }
+}
+
+fn hash_file(name: &str) -> io::Result<String> {
+  let mut reader = open(name)?;
+  let mut hasher = KnotHasher::new();
+  let mut buf = [0; 8192];
+
+  loop {
+    let read = reader.read(&mut buf)?;
+    if read == 0 {
+      break;
+    }
+    hasher.update(&buf[..read]);
+  }
+
+  Ok(to_hex(&hasher.finalize()))
+}
+
+/// Splits a `<hash>  <filename>` line, also accepting the ` *` separator that
+/// `sha256sum` writes in binary mode.
+fn parse_checksum_line(line: &str) -> Option<(&str, &str)> {
+  if line.len() < 34 || !line.is_char_boundary(32) || !line[..32].chars().all(|ch| ch.is_ascii_hexdigit()) {
+    return None;
+  }
+
+  match &line[32..34] {
+    "  " | " *" => Some((&line[..32], &line[34..])),
+    _ => None,
+  }
+}
+
+/// Checks every file listed in `list`, returning how many didn't match, how
+/// many couldn't be read, and how many lines were malformed.
+fn check(list: &str) -> io::Result<(usize, usize, usize)> {
+  check_lines(BufReader::new(open(list)?))
+}
+
+fn check_lines<R: BufRead>(list: R) -> io::Result<(usize, usize, usize)> {
+  let (mut mismatched, mut unreadable, mut malformed) = (0, 0, 0);
+
+  for line in list.lines() {
+    let line = line?;
+    let (expected, name) = match parse_checksum_line(&line) {
+      Some(parsed) => parsed,
+      None => {
+        malformed += 1;
+        continue;
+      },
+    };
+
+    match hash_file(name) {
+      Ok(ref hash) if hash.eq_ignore_ascii_case(expected) => println!("{}: OK", name),
+      Ok(_) => {
+        println!("{}: FAILED", name);
+        mismatched += 1;
+      },
+      Err(e) => {
+        eprintln!("knotsum: {}: {}", name, e);
+        println!("{}: FAILED open or read", name);
+        unreadable += 1;
+      },
+    }
+  }
+
+  Ok((mismatched, unreadable, malformed))
+}
+
+fn plural(count: usize, one: &str, many: &str) -> String {
+  format!("{} {}", count, if count == 1 { one } else { many })
+}
+
+fn main() {
+  let mut args: Vec<String> = env::args().skip(1).collect();
+  let checking = args.iter().any(|arg| arg == "-c" || arg == "--check");
+  args.retain(|arg| arg != "-c" && arg != "--check");
+  if args.is_empty() {
+    args.push("-".to_string());
+  }
+
+  let mut failed = false;
+  for name in &args {
+    if checking {
+      match check(name) {
+        Ok((mismatched, unreadable, malformed)) => {
+          if malformed > 0 {
+            eprintln!("knotsum: WARNING: {} improperly formatted", plural(malformed, "line is", "lines are"));
+          }
+          if unreadable > 0 {
+            eprintln!("knotsum: WARNING: {} could not be read", plural(unreadable, "listed file", "listed files"));
+          }
+          if mismatched > 0 {
+            eprintln!("knotsum: WARNING: {} did NOT match", plural(mismatched, "computed checksum", "computed checksums"));
+          }
+          failed |= mismatched + unreadable + malformed > 0;
+        },
+        Err(e) => {
+          eprintln!("knotsum: {}: {}", name, e);
+          failed = true;
+        },
+      }
+    }
+    else {
+      match hash_file(name) {
+        Ok(hash) => println!("{}  {}", hash, name),
+        Err(e) => {
+          eprintln!("knotsum: {}: {}", name, e);
+          failed = true;
+        },
+      }
+    }
+  }
+
+  if failed {
+    process::exit(1);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+  use std::io::Cursor;
+
+  const HASH: &str = "a2582a3a0e66e6e86e3812dcb672a272";
+
+  #[test]
+  fn parse_checksum_line_accepts_both_separators() {
+    assert_eq!(Some((HASH, "input")), parse_checksum_line(&format!("{}  input", HASH)));
+    assert_eq!(Some((HASH, "input")), parse_checksum_line(&format!("{} *input", HASH)));
+    assert_eq!(Some((HASH, " two  spaces")), parse_checksum_line(&format!("{}   two  spaces", HASH)));
+  }
+
+  #[test]
+  fn parse_checksum_line_rejects_malformed_lines() {
+    assert_eq!(None, parse_checksum_line(""));
+    assert_eq!(None, parse_checksum_line(HASH));
+    assert_eq!(None, parse_checksum_line(&format!("{} input", HASH)));
+    assert_eq!(None, parse_checksum_line(&format!("{}\tinput", HASH)));
+    assert_eq!(None, parse_checksum_line(&format!("{}0  input", HASH)));
+    assert_eq!(None, parse_checksum_line(&format!("{}  input", &HASH[..31])));
+  }
+
+  #[test]
+  fn parse_checksum_line_rejects_non_hex_hashes() {
+    assert_eq!(None, parse_checksum_line("g2582a3a0e66e6e86e3812dcb672a272  input"));
+    assert_eq!(None, parse_checksum_line("a2582a3a0e66e6e86e3812dcb672a27é  input"));
+    assert_eq!(None, parse_checksum_line("a2582a3a0e66e6e86e3812dcb672a2é  input"));
+  }
+
+  #[test]
+  fn check_lines_counts_each_kind_of_failure() {
+    let path = env::temp_dir().join(format!("knotsum-test-{}", process::id()));
+    fs::write(&path, b"").unwrap();
+    let name = path.to_str().unwrap();
+    let missing = format!("{}-missing", name);
+
+    let list = format!(
+      "{hash}  {name}\n{upper} *{name}\n{other}  {name}\n{hash}  {missing}\nnot a checksum line\n",
+      hash = HASH,
+      upper = HASH.to_uppercase(),
+      other = "00000000000000000000000000000000",
+      name = name,
+      missing = missing,
+    );
+    let counts = check_lines(Cursor::new(list)).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!((1, 1, 1), counts);
+  }
+}
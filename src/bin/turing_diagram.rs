@@ -0,0 +1,39 @@
+//! Draws a space-time diagram of a Turing machine, either to the terminal or
+//! as a PPM image. Usage:
+//! `turing_diagram <standard notation | spec file> [rows=40] [every=1] [--ppm path]`.
+
+extern crate adventofcode;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use adventofcode::twentyfive::TuringMachine;
+
+fn main() {
+  let mut args: Vec<String> = env::args().skip(1).collect();
+  let ppm = args.iter().position(|arg| arg == "--ppm").map(|i| {
+    let path = args.get(i + 1).expect("--ppm needs a path").clone();
+    args.drain(i..i + 2);
+    path
+  });
+
+  let machine_arg = args.first().expect("usage: turing_diagram <machine> [rows] [every] [--ppm path]");
+  let mut machine = if Path::new(machine_arg).is_file() {
+    TuringMachine::from(&fs::read_to_string(machine_arg).expect("could not read spec file"))
+  }
+  else {
+    TuringMachine::from_standard_notation(machine_arg)
+  };
+  let rows = args.get(1).map_or(40, |arg| arg.parse().expect("rows must be a number"));
+  let every = args.get(2).map_or(1, |arg| arg.parse().expect("every must be a number"));
+
+  let diagram = machine.space_time_diagram(rows, every);
+  match ppm {
+    Some(path) => {
+      let mut out = BufWriter::new(File::create(&path).expect("could not create image"));
+      diagram.write_ppm(&mut out, 4).expect("could not write image");
+    },
+    None => print!("{}", diagram.to_ansi(72)),
+  }
+}
@@ -1,6 +1,9 @@
 use std::io::BufReader;
+use std::io;
 use std::io::BufRead;
+use std::io::Write;
 use std::fs::File;
+use std::ops::{Deref, DerefMut, Index, IndexMut};
 
 pub fn read_file_contents(filename: &str) -> String {
   let file = File::open(filename).unwrap();
@@ -21,5 +24,267 @@ pub fn read_file_contents(filename: &str) -> String {
 }
 
 pub fn read_file_lines(filename: &str) -> Vec<String> {
-  read_file_contents(filename).lines().map(|s|String::from(s)).collect()
+  read_file_contents(filename).lines().map(String::from).collect()
+}
+
+/// Writes a binary PPM image `width` by `height` pixels across, with each pixel
+/// drawn as a `scale` by `scale` square in the colour `color(x, y)`.
+pub fn write_ppm<W: Write, F: Fn(usize, usize) -> [u8; 3]>(out: &mut W, width: usize, height: usize, scale: usize, color: F) -> io::Result<()> {
+  write!(out, "P6\n{} {}\n255\n", width * scale, height * scale)?;
+
+  let mut line = Vec::with_capacity(width * scale * 3);
+  for y in 0..height {
+    line.clear();
+    for x in 0..width {
+      let rgb = color(x, y);
+      for _ in 0..scale {
+        line.extend_from_slice(&rgb);
+      }
+    }
+    for _ in 0..scale {
+      out.write_all(&line)?;
+    }
+  }
+
+  Ok(())
+}
+
+/// A position on a ring of `len` elements that wraps around as it moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+  position: usize,
+  len: usize,
+}
+
+impl Cursor {
+  pub fn new(position: usize, len: usize) -> Cursor {
+    if len == 0 {
+      panic!("A cursor needs a ring with at least one element");
+    }
+    Cursor { position: position % len, len }
+  }
+
+  pub fn position(&self) -> usize {
+    self.position
+  }
+
+  pub fn advance(&mut self, steps: usize) -> usize {
+    self.position = (self.position + steps % self.len) % self.len;
+    self.position
+  }
+
+  /// Grows the ring by one element just after the cursor and moves onto it.
+  pub fn insert_after(&mut self) -> usize {
+    self.len += 1;
+    self.position += 1;
+    self.position
+  }
+}
+
+/// A fixed number of elements in a circle, over any slice-like storage such
+/// as a `Vec<T>` or a `&mut [T]`. Indexing wraps around, as do slices taken
+/// across the end.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ring<S> {
+  items: S,
+}
+
+impl<T, S: Deref<Target = [T]>> Ring<S> {
+  pub fn new(items: S) -> Ring<S> {
+    Ring { items }
+  }
+
+  pub fn len(&self) -> usize {
+    self.items.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.items.is_empty()
+  }
+
+  pub fn as_slice(&self) -> &[T] {
+    &self.items
+  }
+
+  pub fn into_inner(self) -> S {
+    self.items
+  }
+
+  pub fn cursor(&self, position: usize) -> Cursor {
+    Cursor::new(position, self.len())
+  }
+
+  /// The `length` elements from `from`, as the part before the end of the
+  /// storage and the part wrapped around to its start.
+  pub fn slices(&self, from: usize, length: usize) -> (&[T], &[T]) {
+    let len = self.len();
+    if length > len {
+      panic!("Can't take {} elements from a ring of {}", length, len);
+    }
+    if length == 0 {
+      return (&[], &[]);
+    }
+
+    let from = from % len;
+    if from + length <= len {
+      (&self.items[from..from + length], &[])
+    }
+    else {
+      (&self.items[from..], &self.items[..from + length - len])
+    }
+  }
+
+  /// Every element once, starting from `from` and wrapping around.
+  pub fn iter_from<'a>(&'a self, from: usize) -> impl Iterator<Item = &'a T> where T: 'a {
+    let (head, tail) = self.slices(from, self.len());
+    head.iter().chain(tail)
+  }
+
+  /// Each element paired with the one `offset` places after it.
+  pub fn pairs<'a>(&'a self, offset: usize) -> impl Iterator<Item = (&'a T, &'a T)> where T: 'a {
+    self.items.iter().zip(self.iter_from(offset))
+  }
+}
+
+impl<T, S: DerefMut<Target = [T]>> Ring<S> {
+  /// Reverses the `length` elements from `from`, wrapping around the end.
+  pub fn reverse(&mut self, from: usize, length: usize) {
+    let len = self.len();
+    if length > len {
+      panic!("Can't reverse {} elements of a ring of {}", length, len);
+    }
+    if length == 0 {
+      return;
+    }
+
+    let from = from % len;
+    if from + length <= len {
+      self.items[from..from + length].reverse();
+      return;
+    }
+
+    let mut i = from;
+    let mut j = (from + length - 1) % len;
+    for _ in 0..length / 2 {
+      self.items.swap(i, j);
+      i = if i + 1 == len { 0 } else { i + 1 };
+      j = if j == 0 { len - 1 } else { j - 1 };
+    }
+  }
+
+  pub fn rotate_left(&mut self, steps: usize) {
+    let len = self.len();
+    if len > 0 {
+      self.items.rotate_left(steps % len);
+    }
+  }
+
+  pub fn rotate_right(&mut self, steps: usize) {
+    let len = self.len();
+    if len > 0 {
+      self.items.rotate_right(steps % len);
+    }
+  }
+}
+
+impl<T, S: Deref<Target = [T]>> Index<usize> for Ring<S> {
+  type Output = T;
+
+  fn index(&self, index: usize) -> &T {
+    &self.items[index % self.items.len()]
+  }
+}
+
+impl<T, S: DerefMut<Target = [T]>> IndexMut<usize> for Ring<S> {
+  fn index_mut(&mut self, index: usize) -> &mut T {
+    let len = self.items.len();
+    &mut self.items[index % len]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn write_ppm_scales_pixels() {
+    let mut out = Vec::new();
+    write_ppm(&mut out, 2, 1, 2, |x, _| [x as u8, 0, 9]).unwrap();
+
+    let mut expected = b"P6\n4 2\n255\n".to_vec();
+    for _ in 0..2 {
+      expected.extend_from_slice(&[0, 0, 9, 0, 0, 9, 1, 0, 9, 1, 0, 9]);
+    }
+    assert_eq!(expected, out);
+  }
+
+  #[test]
+  fn cursor_wraps_and_grows() {
+    let mut cursor = Cursor::new(5, 4);
+    assert_eq!(1, cursor.position());
+    assert_eq!(0, cursor.advance(7));
+    assert_eq!(1, cursor.insert_after());
+    assert_eq!(0, cursor.advance(4));
+  }
+
+  #[test]
+  fn indexing_wraps() {
+    let mut ring = Ring::new(vec![1, 2, 3]);
+    ring[4] = 5;
+    assert_eq!(&[1, 5, 3], ring.as_slice());
+    assert_eq!(3, ring[8]);
+  }
+
+  #[test]
+  fn slices_wrap_around_the_end() {
+    let ring = Ring::new(vec![0, 1, 2, 3, 4]);
+    assert_eq!((&[1, 2][..], &[][..]), ring.slices(1, 2));
+    assert_eq!((&[3, 4][..], &[0, 1][..]), ring.slices(8, 4));
+    assert_eq!(vec![&2, &3, &4, &0, &1], ring.iter_from(2).collect::<Vec<_>>());
+    assert_eq!(vec![(&0, &4), (&2, &1), (&4, &3)], ring.pairs(4).step_by(2).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn reverse_works_for_linear_spans_with_odd_length() {
+    let mut ring = Ring::new(vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    ring.reverse(0, 3);
+    assert_eq!(&[3, 2, 1, 4, 5, 6, 7, 8], ring.as_slice());
+  }
+
+  #[test]
+  fn reverse_works_for_circular_spans_with_odd_length() {
+    let mut ring = Ring::new(vec![0, 1, 2, 3, 4, 5]);
+    ring.reverse(5, 3);
+    assert_eq!(&[0, 5, 2, 3, 4, 1], ring.as_slice());
+  }
+
+  #[test]
+  fn reverse_works_for_linear_spans_with_even_length() {
+    let mut ring = Ring::new(vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    ring.reverse(0, 2);
+    assert_eq!(&[2, 1, 3, 4, 5, 6, 7, 8], ring.as_slice());
+  }
+
+  #[test]
+  fn reverse_works_for_circular_spans_with_even_length() {
+    let mut ring = Ring::new(vec![0, 1, 2, 3, 4, 5]);
+    ring.reverse(5, 4);
+    assert_eq!(&[1, 0, 5, 3, 4, 2], ring.as_slice());
+  }
+
+  #[test]
+  fn reverse_works_for_the_whole_ring() {
+    let mut items = [0, 1, 2, 3, 4, 5];
+    Ring::new(&mut items[..]).reverse(4, 6);
+    assert_eq!([1, 0, 5, 4, 3, 2], items);
+  }
+
+  #[test]
+  fn rotation_wraps() {
+    let mut ring = Ring::new(vec![0, 1, 2, 3]);
+    ring.rotate_left(5);
+    assert_eq!(&[1, 2, 3, 0], ring.as_slice());
+    ring.rotate_right(2);
+    assert_eq!(&[3, 0, 1, 2], ring.as_slice());
+  }
 }
@@ -1,7 +1,4 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
+use std::collections::{HashMap, VecDeque};
 use self::Instr::*;
 
 #[derive(Debug, Clone)]
@@ -15,23 +12,6 @@ enum Instr {
   Jump(String, String)
 }
 
-#[derive(Debug)]
-struct InstructionCounter {
-  snd: usize,
-  set: usize,
-  add: usize,
-  mul: usize,
-  modulus: usize,
-  rcv: usize,
-  jump: usize
-}
-
-impl InstructionCounter {
-  fn new() -> Self {
-    Self {snd: 0, set: 0, add: 0, mul: 0, modulus: 0, rcv: 0, jump: 0}
-  }
-}
-
 type Registers = HashMap<char, i64>;
 
 fn compile_instruction(instruction: &str) -> Instr {
@@ -77,13 +57,11 @@ fn compile_instruction(instruction: &str) -> Instr {
 }
 
 fn init_register(registers: &mut Registers, reg: char) {
-  if !registers.contains_key(&reg) {
-    registers.insert(reg, 0);
-  }
+  registers.entry(reg).or_insert(0);
 }
 
 fn register_val(registers: &mut Registers, identifier: &str) -> i64 {
-  let reg = identifier.chars().nth(0).unwrap();
+  let reg = identifier.chars().next().unwrap();
   if reg.is_alphabetic() {
     init_register(registers, reg);
     *registers.get(&reg).unwrap()
@@ -93,207 +71,256 @@ fn register_val(registers: &mut Registers, identifier: &str) -> i64 {
   }
 }
 
-fn recover_sound(registers: &mut Registers, program: &Vec<Instr>) -> i64 {
-  let mut pc = 0;
-  let mut last_sound = 0;
+/// How `snd` and `rcv` are interpreted. In `Sound` mode, `snd` plays a sound and
+/// `rcv` recovers the last one played (if its register is nonzero). In `Message`
+/// mode, `snd` queues a value for the other program and `rcv` waits for one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Semantics {
+  Sound,
+  Message
+}
 
-  loop {
-    match program[pc] {
-      Rcv(ref id) => {
-        if register_val(registers, id) != 0 {
-          return last_sound;
-        }
-      },
-      Snd(ref id) => {
-        last_sound = register_val(registers, id);
-      },
-      Set(ref reg, ref val_id) => {
-        let register = reg.chars().nth(0).unwrap();
-        let value = register_val(registers, val_id);
-        registers.insert(register, value);
-      },
-      Jump(ref reg, ref val) => {
-        let reg_val = register_val(registers, reg);
-        let val_val = register_val(registers, val);
-        if reg_val > 0 {
-          if val_val > 0 {
-            pc += val_val as usize;
-          }
-          if val_val < 0 {
-            pc -= -val_val as usize;
-          }
-          // skip the increment phase of this instr
-          continue;
-        }
-      },
-      Mod(ref reg, ref val) => {
-        let register = reg.chars().nth(0).unwrap();
-        let reg_val = register_val(registers, reg);
-        let val_val = register_val(registers, val);
-        let prod = reg_val % val_val;
-        registers.insert(register, prod);
-      },
-      Mul(ref reg, ref val) => {
-        let register = reg.chars().nth(0).unwrap();
-        let reg_val = register_val(registers, reg);
-        let val_val = register_val(registers, val);
-        let prod = reg_val * val_val;
-        registers.insert(register, prod);
-      },
-      Add(ref reg, ref val) => {
-        let register = reg.chars().nth(0).unwrap();
-        let reg_val = register_val(registers, reg);
-        let val_val = register_val(registers, val);
-        let sum = reg_val + val_val;
-        registers.insert(register, sum);
-      }
-    }
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Outcome {
+  /// A `rcv` with a nonzero register recovered the last sound played.
+  Recovered(i64),
+  /// The program counter left the program.
+  Halted,
+  /// A `rcv` found the inbox empty; running again resumes at the same `rcv`.
+  Blocked,
+  /// The step limit was reached before any of the above.
+  StepLimit
+}
 
-    // increment program counter
-    pc += 1;
-  }
+struct Duet<'a> {
+  program: &'a [Instr],
+  semantics: Semantics,
+  registers: Registers,
+  pc: i64,
+  last_sound: Option<i64>,
+  inbox: VecDeque<i64>,
+  outbox: VecDeque<i64>,
+  steps: usize
 }
 
-fn run_parallel(program: &Vec<Instr>, processes: usize) -> Arc<Mutex<Vec<InstructionCounter>>> {
-  let counters = Arc::new(Mutex::new(vec![
-    InstructionCounter::new(),
-    InstructionCounter::new()
-  ]));
-  let msg_queue: Arc<Mutex<Vec<(usize, i64)>>> = Arc::new(Mutex::new(vec![]));
-  let proc_state = Arc::new(Mutex::new(vec!["working".to_string(), "working".to_string()]));
-
-  for pid in 0..processes {
-    let thread_program = program.clone();
-    let thread_proc_state = proc_state.clone();
-    let thread_msg_queue = msg_queue.clone();
-    let thread_counters = counters.clone();
-
-    thread::spawn(move || {
-      let registers = &mut Registers::new();
-      registers.insert('p', pid as i64);
-      let mut program_counter = 0;
-
-      while program_counter < thread_program.len() {
-        match thread_program[program_counter] {
-          Rcv(ref reg) => {
-            thread_counters.lock().unwrap()[pid].rcv += 1;
-            let mut thread_states = thread_proc_state.lock().unwrap();
-            thread_states[pid] = "waiting".to_string();
-            loop {
-              let mut queue = thread_msg_queue.lock().unwrap();
-              match queue.iter().position(|msg| msg.0 == pid) {
-                Some(msg_index) => {
-                  let (_, value) = queue[msg_index];
-                  println!("Thread {} received value {}", pid, value);
-                  let register = reg.chars().nth(0).unwrap();
-                  registers.insert(register, value);
-                  queue.remove(msg_index);
-                  thread_states[pid] = "working".to_string();
-                  break;
-                },
-                None => {
-                  // release the lock before going to sleep, so other thread has a chance to snd
-                  drop(queue);
-                  thread::sleep(Duration::from_millis(20));
-                }
-              }
-            }
-          },
-          Snd(ref id) => {
-            thread_counters.lock().unwrap()[pid].snd += 1;
-            let other_pid = (pid + 1) % processes;
-            let value = register_val(registers, id);
-            thread_msg_queue.lock().unwrap().push((other_pid, value));
-            println!("Thread {} sent value {}", pid, value);
-          },
-          Set(ref reg, ref val_id) => {
-            thread_counters.lock().unwrap()[pid].set += 1;
-            let register = reg.chars().nth(0).unwrap();
-            let value = register_val(registers, val_id);
-            registers.insert(register, value);
-          },
-          Jump(ref reg, ref val) => {
-            thread_counters.lock().unwrap()[pid].jump += 1;
-            let reg_val = register_val(registers, reg);
-            let val_val = register_val(registers, val);
-            if reg_val > 0 {
-              if val_val > 0 {
-                program_counter += val_val as usize;
-              }
-              if val_val < 0 {
-                program_counter -= -val_val as usize;
+impl<'a> Duet<'a> {
+  fn new(program: &'a [Instr], semantics: Semantics) -> Self {
+    Duet {
+      program,
+      semantics,
+      registers: Registers::new(),
+      pc: 0,
+      last_sound: None,
+      inbox: VecDeque::new(),
+      outbox: VecDeque::new(),
+      steps: 0
+    }
+  }
+
+  /// Runs until the program recovers a sound, halts, blocks on an empty inbox,
+  /// or executes `max_steps` more instructions.
+  fn run(&mut self, max_steps: usize) -> Outcome {
+    let limit = self.steps + max_steps;
+
+    loop {
+      if self.pc < 0 || self.pc >= self.program.len() as i64 {
+        return Outcome::Halted;
+      }
+      if self.steps == limit {
+        return Outcome::StepLimit;
+      }
+
+      let registers = &mut self.registers;
+      let mut next_pc = self.pc + 1;
+
+      match self.program[self.pc as usize] {
+        Rcv(ref id) => match self.semantics {
+          Semantics::Sound => {
+            if register_val(registers, id) != 0 {
+              if let Some(sound) = self.last_sound {
+                self.steps += 1;
+                self.pc = next_pc;
+                return Outcome::Recovered(sound);
               }
-              // skip the increment phase of this instr
-              continue;
             }
           },
-          Mod(ref reg, ref val) => {
-            thread_counters.lock().unwrap()[pid].modulus += 1;
-            let register = reg.chars().nth(0).unwrap();
-            let reg_val = register_val(registers, reg);
-            let val_val = register_val(registers, val);
-            let prod = reg_val % val_val;
-            registers.insert(register, prod);
-          },
-          Mul(ref reg, ref val) => {
-            thread_counters.lock().unwrap()[pid].mul += 1;
-            let register = reg.chars().nth(0).unwrap();
-            let reg_val = register_val(registers, reg);
-            let val_val = register_val(registers, val);
-            let prod = reg_val * val_val;
-            registers.insert(register, prod);
-          },
-          Add(ref reg, ref val) => {
-            thread_counters.lock().unwrap()[pid].add += 1;
-            let register = reg.chars().nth(0).unwrap();
-            let reg_val = register_val(registers, reg);
-            let val_val = register_val(registers, val);
-            let sum = reg_val + val_val;
-            registers.insert(register, sum);
+          Semantics::Message => match self.inbox.pop_front() {
+            Some(value) => {
+              let register = id.chars().next().unwrap();
+              registers.insert(register, value);
+            },
+            None => return Outcome::Blocked
           }
+        },
+        Snd(ref id) => {
+          let value = register_val(registers, id);
+          match self.semantics {
+            Semantics::Sound => self.last_sound = Some(value),
+            Semantics::Message => self.outbox.push_back(value)
+          }
+        },
+        Set(ref reg, ref val_id) => {
+          let register = reg.chars().next().unwrap();
+          let value = register_val(registers, val_id);
+          registers.insert(register, value);
+        },
+        Jump(ref reg, ref val) => {
+          let reg_val = register_val(registers, reg);
+          let val_val = register_val(registers, val);
+          if reg_val > 0 {
+            next_pc = self.pc + val_val;
+          }
+        },
+        Mod(ref reg, ref val) => {
+          let register = reg.chars().next().unwrap();
+          let reg_val = register_val(registers, reg);
+          let val_val = register_val(registers, val);
+          registers.insert(register, reg_val % val_val);
+        },
+        Mul(ref reg, ref val) => {
+          let register = reg.chars().next().unwrap();
+          let reg_val = register_val(registers, reg);
+          let val_val = register_val(registers, val);
+          registers.insert(register, reg_val * val_val);
+        },
+        Add(ref reg, ref val) => {
+          let register = reg.chars().next().unwrap();
+          let reg_val = register_val(registers, reg);
+          let val_val = register_val(registers, val);
+          registers.insert(register, reg_val + val_val);
         }
-        program_counter += 1;
-        thread::sleep(Duration::from_millis(10));
       }
 
-      // all done. update thread_state
-      thread_proc_state.lock().unwrap()[pid] = "done".to_string();
-    });
+      self.steps += 1;
+      self.pc = next_pc;
+    }
   }
+}
 
+fn recover_sound(program: &[Instr], max_steps: usize) -> Outcome {
+  Duet::new(program, Semantics::Sound).run(max_steps)
+}
+
+/// Runs two copies of the program in `Message` mode, feeding each one's sends
+/// to the other, until both are blocked or halted. Returns how many values
+/// each program sent, or `None` if the step limit was reached first.
+fn run_pair(program: &[Instr], max_steps: usize) -> Option<[usize; 2]> {
+  let mut procs = [
+    Duet::new(program, Semantics::Message),
+    Duet::new(program, Semantics::Message)
+  ];
+  procs[1].registers.insert('p', 1);
+
+  let mut sent = [0, 0];
   loop {
-    let p_states = proc_state.lock().unwrap();
-    match (p_states[0].as_str(), p_states[1].as_str()) {
-      // terminal conditions:
-      // both threads done
-      ("done", "done") => break,
-
-      // a thread is waiting for a thread that will never send
-      ("done", "waiting") => break,
-      ("waiting", "done") => break,
-
-      // both threads waiting on each other
-      ("waiting", "waiting") => break,
-
-      // everything else, keep waiting
-      (_, _) => {
-        println!("States = {:?}", *p_states);
-        println!("Counter = {:?}", *counters.lock().unwrap());
-        drop(p_states);
-        thread::sleep(Duration::from_millis(500));
+    let mut progressed = false;
+
+    for pid in 0..2 {
+      let before = procs[pid].steps;
+      let remaining = max_steps - (procs[0].steps + procs[1].steps);
+      if procs[pid].run(remaining) == Outcome::StepLimit {
+        return None;
       }
+      progressed |= procs[pid].steps != before;
+
+      let msgs: Vec<i64> = procs[pid].outbox.drain(..).collect();
+      sent[pid] += msgs.len();
+      procs[1 - pid].inbox.extend(msgs);
+    }
+
+    if !progressed {
+      return Some(sent);
     }
   }
+}
+
+const MAX_STEPS: usize = 10_000_000;
+
+fn main_1(program: &[Instr]) {
+  match recover_sound(program, MAX_STEPS) {
+    Outcome::Recovered(sound) => println!("Sound recovered = {}", sound),
+    outcome => println!("No sound recovered: {:?}", outcome)
+  }
+}
 
-  counters
+fn main_2(program: &[Instr]) {
+  match run_pair(program, MAX_STEPS) {
+    Some(sent) => println!("Program 1 sent {} values", sent[1]),
+    None => println!("Programs did not finish within {} steps", MAX_STEPS)
+  }
 }
 
-pub fn main () {
+pub fn main() {
   let program: Vec<Instr> = include_str!("../input/eighteen").lines().map(compile_instruction).collect();
-  // let mut registers = HashMap::new();
-  // let sound = recover_sound(&mut registers, &program);
-  // println!("Sound recovered = {}", sound);
+  main_1(&program);
+  main_2(&program);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn compile(src: &str) -> Vec<Instr> {
+    src.lines().map(compile_instruction).collect()
+  }
+
+  #[test]
+  fn recover_sound_works() {
+    let program = compile("set a 1
+add a 2
+mul a a
+mod a 5
+snd a
+set a 0
+rcv a
+jgz a -1
+set a 1
+jgz a -2");
+    assert_eq!(Outcome::Recovered(4), recover_sound(&program, 100));
+  }
+
+  #[test]
+  fn recover_sound_halts_when_pc_leaves_program() {
+    let program = compile("snd 5
+rcv 0
+jgz 1 -3");
+    assert_eq!(Outcome::Halted, recover_sound(&program, 100));
 
-  let counters = run_parallel(&program, 2);
-  println!("Counter for second thread = {:?}", counters.lock().unwrap()[1]);
+    let program = compile("set a 3
+snd a
+rcv 0");
+    assert_eq!(Outcome::Halted, recover_sound(&program, 100));
+  }
+
+  #[test]
+  fn recover_sound_stops_at_step_limit() {
+    let program = compile("snd 5
+rcv 0
+jgz 1 -1");
+    assert_eq!(Outcome::StepLimit, recover_sound(&program, 100));
+  }
+
+  #[test]
+  fn message_mode_blocks_and_resumes() {
+    let program = compile("rcv a
+snd a");
+    let mut duet = Duet::new(&program, Semantics::Message);
+    assert_eq!(Outcome::Blocked, duet.run(100));
+
+    duet.inbox.push_back(7);
+    assert_eq!(Outcome::Halted, duet.run(100));
+    assert_eq!(Some(&7), duet.outbox.front());
+  }
+
+  #[test]
+  fn run_pair_works() {
+    let program = compile("snd 1
+snd 2
+snd p
+rcv a
+rcv b
+rcv c
+rcv d");
+    assert_eq!(Some([3, 3]), run_pair(&program, 100));
+  }
 }
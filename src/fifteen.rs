@@ -1,17 +1,116 @@
+use std::fmt;
+use std::iter::Take;
+use rayon::prelude::*;
 
-struct Generator {
+/// The Mersenne prime 2^31 - 1.
+const MODULUS: usize = 2147483647;
+
+/// Values generated at a time by `count_matching_pairs_batched`.
+const BATCH: usize = 4096;
+
+/// Independent sequences interleaved by `Generator::fill`.
+const LANES: usize = 8;
+
+/// `x mod 2^31 - 1` for any `x` below 2^62, such as the product of two values
+/// below the modulus. Since 2^31 is 1 more than the modulus, the bits above
+/// the low 31 can be added back on instead of dividing.
+fn mersenne_mod(x: u64) -> u64 {
+  let m = MODULUS as u64;
+  let x = (x & m) + (x >> 31);
+  let x = (x & m) + (x >> 31);
+  if x >= m { x - m } else { x }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Generator {
   seed: usize,
   factor: usize
 }
 
+/// `base^exp mod modulus`, by repeated squaring.
+fn mod_pow(mut base: usize, mut exp: usize, modulus: usize) -> usize {
+  let mut result = 1 % modulus;
+  base %= modulus;
+  while exp > 0 {
+    if exp & 1 == 1 {
+      result = result * base % modulus;
+    }
+    base = base * base % modulus;
+    exp >>= 1;
+  }
+  result
+}
+
 impl Generator {
-  fn new(seed: usize, factor: usize) -> Generator {
+  pub fn new(seed: usize, factor: usize) -> Generator {
     Generator { seed, factor }
   }
+
+  /// Jumps straight past the next `n` values in O(log n) steps, where
+  /// `Iterator::skip` would generate them: skipping `n` values multiplies the
+  /// seed by `factor^n`.
+  pub fn skip_ahead(self, n: usize) -> Generator {
+    let seed = self.seed * mod_pow(self.factor, n, MODULUS) % MODULUS;
+    Generator { seed, factor: self.factor }
+  }
+
+  /// Splits the next `samples` values into `k` consecutive chunks that can be
+  /// generated independently. The first `samples % k` chunks are one value
+  /// longer than the rest.
+  pub fn split(self, samples: usize, k: usize) -> Vec<Take<Generator>> {
+    chunk_bounds(samples, k).into_iter()
+      .map(|(start, len)| self.clone().skip_ahead(start).take(len))
+      .collect()
+  }
+
+  /// Fills `out` with the next values, as the iterator would produce them.
+  /// The values are generated `LANES` at a time, each lane jumping `LANES`
+  /// values ahead per step, so that the lanes don't wait on each other.
+  pub fn fill(&mut self, out: &mut [u32]) {
+    let factor = self.factor as u64;
+    let jump = mod_pow(self.factor, LANES, MODULUS) as u64;
+
+    let mut lanes = [0u64; LANES];
+    let mut seed = self.seed as u64;
+    for lane in lanes.iter_mut() {
+      seed = mersenne_mod(seed * factor);
+      *lane = seed;
+    }
+
+    let mut chunks = out.chunks_exact_mut(LANES);
+    for chunk in &mut chunks {
+      for (val, lane) in chunk.iter_mut().zip(lanes.iter_mut()) {
+        *val = *lane as u32;
+        *lane = mersenne_mod(*lane * jump);
+      }
+    }
+    for (val, lane) in chunks.into_remainder().iter_mut().zip(&lanes) {
+      *val = *lane as u32;
+    }
+
+    if let Some(&last) = out.last() {
+      self.seed = last as usize;
+    }
+  }
+}
+
+/// The start and length of each of `k` consecutive chunks of `samples`.
+fn chunk_bounds(samples: usize, k: usize) -> Vec<(usize, usize)> {
+  if k == 0 {
+    panic!("Can't split {} samples into 0 chunks", samples);
+  }
+  let (len, extra) = (samples / k, samples % k);
+  let mut start = 0;
+
+  (0..k).map(|i| {
+    let chunk_len = len + if i < extra { 1 } else { 0 };
+    start += chunk_len;
+    (start - chunk_len, chunk_len)
+  }).collect()
 }
 
 fn compute_next_val(seed: usize, factor: usize) -> usize {
-  seed * factor % 2147483647
+  mersenne_mod(seed as u64 * factor as u64) as usize
 }
 
 impl Iterator for Generator {
@@ -23,7 +122,7 @@ impl Iterator for Generator {
   }
 }
 
-fn count_matching_pairs<I1, I2>(a: I1, b: I2, sample: usize) -> usize
+pub fn count_matching_pairs<I1, I2>(a: I1, b: I2, sample: usize) -> usize
   where I1: Iterator<Item=usize>,
   I2: Iterator<Item=usize> {
   a.zip(b).take(sample).fold(0, |n, (x, y)| {
@@ -38,20 +137,287 @@ fn count_matching_pairs<I1, I2>(a: I1, b: I2, sample: usize) -> usize
   })
 }
 
-fn main_1() {
-  let mut gen_a = Generator::new(618, 16807);
-  let mut gen_b = Generator::new(814, 48271);
+/// `count_matching_pairs` for two unfiltered generators, filling a batch of
+/// values from each at a time and comparing the batches, in a loop the
+/// compiler can vectorize.
+pub fn count_matching_pairs_batched(mut a: Generator, mut b: Generator, sample: usize) -> usize {
+  let (mut buf_a, mut buf_b) = ([0u32; BATCH], [0u32; BATCH]);
+  let mut matches = 0;
+  let mut remaining = sample;
+
+  while remaining > 0 {
+    let len = remaining.min(BATCH);
+    a.fill(&mut buf_a[..len]);
+    b.fill(&mut buf_b[..len]);
+    matches += buf_a[..len].iter().zip(&buf_b[..len])
+      .filter(|&(x, y)| (x ^ y) & 0xFFFF == 0)
+      .count();
+    remaining -= len;
+  }
 
-  let pairs = count_matching_pairs(&mut gen_a, &mut gen_b, 40_000_000);
-  println!("Number of matching pairs = {}", pairs);
+  matches
 }
 
-fn main_2() {
-  let mut gen_a = Generator::new(618, 16807).filter(|v| v % 4 == 0);
-  let mut gen_b = Generator::new(814, 48271).filter(|v| v % 8 == 0);
+/// `count_matching_pairs_batched`, with the sample split into chunks that are
+/// counted in parallel.
+pub fn count_matching_pairs_parallel(a: Generator, b: Generator, sample: usize) -> usize {
+  let chunks = 4 * rayon::current_num_threads();
+
+  chunk_bounds(sample, chunks).into_par_iter()
+    .map(|(start, len)| count_matching_pairs_batched(a.clone().skip_ahead(start), b.clone().skip_ahead(start), len))
+    .sum()
+}
+
+/// Which of a generator's values are handed to the judge. A generator whose
+/// filter never accepts a value leaves the judge waiting forever.
+pub enum Filter {
+  All,
+  MultipleOf(usize),
+  Predicate(Box<dyn Fn(usize) -> bool>),
+}
+
+impl Filter {
+  fn accepts(&self, val: usize) -> bool {
+    match *self {
+      Filter::All => true,
+      Filter::MultipleOf(n) => val % n == 0,
+      Filter::Predicate(ref predicate) => predicate(val),
+    }
+  }
+}
+
+pub struct GeneratorSpec {
+  pub name: String,
+  pub seed: usize,
+  pub factor: usize,
+  pub filter: Filter,
+}
+
+/// The factors of the puzzle's generators, for input lines that don't give one.
+fn default_factor(name: &str) -> Option<usize> {
+  match name {
+    "A" => Some(16807),
+    "B" => Some(48271),
+    _ => None,
+  }
+}
+
+/// Parses "Generator A starts with 618", optionally followed by "and
+/// multiplies by 16807".
+fn parse_generator(line: &str) -> GeneratorSpec {
+  let words: Vec<&str> = line.split_whitespace().collect();
+  let (name, seed, factor) = match words.as_slice() {
+    ["Generator", name, "starts", "with", seed] => (name, seed, None),
+    ["Generator", name, "starts", "with", seed, "and", "multiplies", "by", factor] => (name, seed, Some(factor)),
+    _ => panic!("Invalid generator line: {}", line),
+  };
+
+  let factor = match factor {
+    Some(factor) => factor.parse().unwrap(),
+    None => match default_factor(name) {
+      Some(factor) => factor,
+      None => panic!("Generator {} needs a factor, as in \"and multiplies by 16807\"", name),
+    },
+  };
+
+  GeneratorSpec {
+    name: name.to_string(),
+    seed: seed.parse().unwrap(),
+    factor,
+    filter: Filter::All,
+  }
+}
+
+/// Compares the values of every pair of generators, a sample at a time. The
+/// `n`th values of two generators match if their low `mask_bits` bits do.
+pub struct Judge {
+  pub generators: Vec<GeneratorSpec>,
+  pub mask_bits: u32,
+  pub sample: usize,
+}
+
+impl Judge {
+  /// Parses one generator per line, and sets up the puzzle's first part:
+  /// 40 million unfiltered values, compared on their low 16 bits.
+  pub fn from(input: &str) -> Judge {
+    let generators = input.lines().map(str::trim).filter(|line| !line.is_empty())
+      .map(parse_generator)
+      .collect();
+
+    Judge { generators, mask_bits: 16, sample: 40_000_000 }
+  }
+
+  fn generator_mut(&mut self, name: &str) -> &mut GeneratorSpec {
+    match self.generators.iter_mut().find(|spec| spec.name == name) {
+      Some(spec) => spec,
+      None => panic!("No generator named {}", name),
+    }
+  }
+
+  pub fn factor(mut self, name: &str, factor: usize) -> Judge {
+    self.generator_mut(name).factor = factor;
+    self
+  }
+
+  pub fn filter(mut self, name: &str, filter: Filter) -> Judge {
+    self.generator_mut(name).filter = filter;
+    self
+  }
+
+  pub fn mask_bits(mut self, bits: u32) -> Judge {
+    if bits == 0 || bits > 31 {
+      panic!("Values have 31 bits, so the mask must be between 1 and 31 bits, not {}", bits);
+    }
+    self.mask_bits = bits;
+    self
+  }
+
+  pub fn sample(mut self, sample: usize) -> Judge {
+    self.sample = sample;
+    self
+  }
+
+  /// Judges the sample, counting matches for each pair of generators.
+  pub fn run(&self) -> JudgeReport {
+    let n = self.generators.len();
+    let pairs: Vec<(usize, usize)> = (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j))).collect();
+    let mask = (1usize << self.mask_bits) - 1;
+    let (matches, first_matches) = if self.generators.iter().all(|spec| matches!(spec.filter, Filter::All)) {
+      // unfiltered values line up, so each chunk of the sample can start from
+      // generators skipped ahead to it, and be generated a batch at a time
+      let generators: Vec<Generator> = self.generators.iter().map(|spec| Generator::new(spec.seed, spec.factor)).collect();
+      let chunks = 4 * rayon::current_num_threads();
+      let counts: Vec<_> = chunk_bounds(self.sample, chunks).into_par_iter()
+        .map(|(start, len)| {
+          let mut skipped: Vec<Generator> = generators.iter().map(|generator| generator.clone().skip_ahead(start)).collect();
+          judge_batches(&mut skipped, &pairs, mask, start, len)
+        })
+        .collect();
+
+      counts.into_iter().fold((vec![0; pairs.len()], vec![None; pairs.len()]), |(mut matches, mut first_matches), (chunk_matches, chunk_firsts)| {
+        for p in 0..pairs.len() {
+          matches[p] += chunk_matches[p];
+          first_matches[p] = first_matches[p].or(chunk_firsts[p]);
+        }
+        (matches, first_matches)
+      })
+    }
+    else {
+      let mut generators: Vec<_> = self.generators.iter()
+        .map(|spec| Generator::new(spec.seed, spec.factor).filter(move |&val| spec.filter.accepts(val)))
+        .collect();
+      judge_values(&mut generators, &pairs, mask, 0, self.sample)
+    };
+
+    let pairs = pairs.into_iter().zip(matches).zip(first_matches)
+      .map(|(((i, j), matches), first_match)| PairStats {
+        a: self.generators[i].name.clone(),
+        b: self.generators[j].name.clone(),
+        matches,
+        first_match,
+        sample: self.sample,
+        expected: self.sample as f64 / (1u64 << self.mask_bits) as f64,
+      })
+      .collect();
+
+    JudgeReport { pairs }
+  }
+}
+
+/// Compares the next `len` values of every pair of generators, returning the
+/// matches for each pair and the index of the first, counting from `start`.
+fn judge_values<I: Iterator<Item=usize>>(generators: &mut [I], pairs: &[(usize, usize)], mask: usize, start: usize, len: usize) -> (Vec<usize>, Vec<Option<usize>>) {
+  let mut matches = vec![0; pairs.len()];
+  let mut first_matches = vec![None; pairs.len()];
+  let mut vals = vec![0; generators.len()];
+
+  for step in start..start + len {
+    for (val, generator) in vals.iter_mut().zip(generators.iter_mut()) {
+      *val = generator.next().unwrap();
+    }
+    for (p, &(i, j)) in pairs.iter().enumerate() {
+      if (vals[i] ^ vals[j]) & mask == 0 {
+        matches[p] += 1;
+        first_matches[p].get_or_insert(step);
+      }
+    }
+  }
+
+  (matches, first_matches)
+}
+
+/// `judge_values` for unfiltered generators, filling a batch of values from
+/// each at a time and comparing the batches.
+fn judge_batches(generators: &mut [Generator], pairs: &[(usize, usize)], mask: usize, start: usize, len: usize) -> (Vec<usize>, Vec<Option<usize>>) {
+  let mut matches = vec![0; pairs.len()];
+  let mut first_matches = vec![None; pairs.len()];
+  let mut batches = vec![[0u32; BATCH]; generators.len()];
+  let mut done = 0;
+
+  while done < len {
+    let batch_len = (len - done).min(BATCH);
+    for (generator, batch) in generators.iter_mut().zip(batches.iter_mut()) {
+      generator.fill(&mut batch[..batch_len]);
+    }
+    for (p, &(i, j)) in pairs.iter().enumerate() {
+      let is_match = |(x, y): (&u32, &u32)| (x ^ y) as usize & mask == 0;
+      let (a, b) = (&batches[i][..batch_len], &batches[j][..batch_len]);
+      let count = a.iter().zip(b).filter(|&pair| is_match(pair)).count();
+      if count > 0 && first_matches[p].is_none() {
+        first_matches[p] = a.iter().zip(b).position(is_match).map(|k| start + done + k);
+      }
+      matches[p] += count;
+    }
+    done += batch_len;
+  }
+
+  (matches, first_matches)
+}
+
+/// How often two generators matched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairStats {
+  pub a: String,
+  pub b: String,
+  pub matches: usize,
+  /// Index in the sample of the first match.
+  pub first_match: Option<usize>,
+  pub sample: usize,
+  /// Matches expected from generators whose low bits were uniformly random.
+  pub expected: f64,
+}
+
+/// Match statistics for every pair of generators, in the order the
+/// generators were given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JudgeReport {
+  pub pairs: Vec<PairStats>,
+}
+
+impl fmt::Display for JudgeReport {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for pair in &self.pairs {
+      let first = pair.first_match.map_or("none".to_string(), |step| step.to_string());
+      writeln!(f, "{} vs {}: {} matches in {} ({:.1} expected, {:.3}x), first at {}",
+        pair.a, pair.b, pair.matches, pair.sample, pair.expected, pair.matches as f64 / pair.expected, first)?;
+    }
+    Ok(())
+  }
+}
 
-  let pairs = count_matching_pairs(&mut gen_a, &mut gen_b, 5_000_000);
-  println!("Number of filtered matching pairs = {}", pairs);
+fn main_1() {
+  let report = Judge::from(include_str!("../input/fifteen")).run();
+  println!("Number of matching pairs = {}", report.pairs[0].matches);
+}
+
+fn main_2() {
+  let report = Judge::from(include_str!("../input/fifteen"))
+    .filter("A", Filter::MultipleOf(4))
+    .filter("B", Filter::MultipleOf(8))
+    .sample(5_000_000)
+    .run();
+  println!("Number of filtered matching pairs = {}", report.pairs[0].matches);
+  print!("{}", report);
 }
 
 pub fn main () {
@@ -108,4 +474,138 @@ mod test {
 
     assert_eq!(1, count_matching_pairs(&mut gen_a, &mut gen_b, 1056));
   }
+
+  #[test]
+  fn skip_ahead_jumps_past_values() {
+    let mut gen_a = Generator::new(65, 16807);
+    let mut skipped = gen_a.clone().skip_ahead(2);
+    assert_eq!(Some(245556042), skipped.next());
+    let mut skipped = gen_a.clone().skip_ahead(999);
+    assert_eq!(gen_a.clone().nth(999), skipped.next());
+    assert_eq!(gen_a.clone(), gen_a.clone().skip_ahead(0));
+
+    gen_a.next();
+    assert_eq!(Generator::new(65, 16807).skip_ahead(1), gen_a);
+  }
+
+  #[test]
+  fn split_covers_the_stream() {
+    let chunks = Generator::new(8921, 48271).split(1003, 4);
+    let lens: Vec<usize> = chunks.iter().map(|chunk| chunk.clone().count()).collect();
+    assert_eq!(vec![251, 251, 251, 250], lens);
+
+    let joined: Vec<usize> = chunks.into_iter().flatten().collect();
+    let sequential: Vec<usize> = Generator::new(8921, 48271).take(1003).collect();
+    assert_eq!(sequential, joined);
+  }
+
+  #[test]
+  #[should_panic(expected = "Can't split 10 samples into 0 chunks")]
+  fn split_rejects_zero_chunks() {
+    Generator::new(65, 16807).split(10, 0);
+  }
+
+  #[test]
+  fn count_matching_pairs_parallel_matches_sequential() {
+    let gen_a = Generator::new(65, 16807);
+    let gen_b = Generator::new(8921, 48271);
+
+    assert_eq!(1, count_matching_pairs_parallel(gen_a.clone(), gen_b.clone(), 5));
+    assert_eq!(
+      count_matching_pairs(gen_a.clone(), gen_b.clone(), 200_000),
+      count_matching_pairs_parallel(gen_a, gen_b, 200_000));
+  }
+
+  #[test]
+  fn mersenne_mod_works() {
+    let m = MODULUS as u64;
+    for &x in &[0, 1, m - 1, m, m + 1, 2 * m, (m - 1) * (m - 1), (1 << 62) - 1, 16807 * 1092455] {
+      assert_eq!(x % m, mersenne_mod(x));
+    }
+  }
+
+  #[test]
+  fn fill_matches_the_iterator() {
+    let mut gen_b = Generator::new(8921, 48271);
+    let mut values = [0u32; 7];
+    gen_b.fill(&mut values[..5]);
+    gen_b.fill(&mut values[5..]);
+
+    let expected: Vec<u32> = Generator::new(8921, 48271).take(7).map(|val| val as u32).collect();
+    assert_eq!(expected, values.to_vec());
+    assert_eq!(Generator::new(8921, 48271).skip_ahead(7), gen_b);
+  }
+
+  #[test]
+  fn count_matching_pairs_batched_matches_sequential() {
+    let gen_a = Generator::new(65, 16807);
+    let gen_b = Generator::new(8921, 48271);
+
+    assert_eq!(1, count_matching_pairs_batched(gen_a.clone(), gen_b.clone(), 5));
+    assert_eq!(
+      count_matching_pairs(gen_a.clone(), gen_b.clone(), 10_000),
+      count_matching_pairs_batched(gen_a, gen_b, 10_000));
+  }
+
+  const EXAMPLE: &str = "Generator A starts with 65
+Generator B starts with 8921";
+
+  #[test]
+  fn judge_parses_generators() {
+    let judge = Judge::from("Generator A starts with 65
+Generator B starts with 8921
+Generator C starts with 1 and multiplies by 69621
+");
+    let specs: Vec<(&str, usize, usize)> = judge.generators.iter()
+      .map(|spec| (spec.name.as_str(), spec.seed, spec.factor))
+      .collect();
+    assert_eq!(vec![("A", 65, 16807), ("B", 8921, 48271), ("C", 1, 69621)], specs);
+    assert_eq!(16, judge.mask_bits);
+    assert_eq!(40_000_000, judge.sample);
+  }
+
+  #[test]
+  #[should_panic(expected = "Generator C needs a factor")]
+  fn judge_needs_factors_for_unknown_generators() {
+    Judge::from("Generator C starts with 1");
+  }
+
+  #[test]
+  fn judge_runs_the_examples() {
+    let report = Judge::from(EXAMPLE).sample(5).run();
+    assert_eq!(1, report.pairs[0].matches);
+    assert_eq!(Some(2), report.pairs[0].first_match);
+
+    let report = Judge::from(EXAMPLE)
+      .filter("A", Filter::MultipleOf(4))
+      .filter("B", Filter::Predicate(Box::new(|val| val % 8 == 0)))
+      .sample(1056)
+      .run();
+    assert_eq!(1, report.pairs[0].matches);
+    assert_eq!(Some(1055), report.pairs[0].first_match);
+  }
+
+  #[test]
+  fn judge_compares_every_pair() {
+    let judge = Judge::from(&format!("{}\nGenerator C starts with 1 and multiplies by 69621", EXAMPLE))
+      .mask_bits(8)
+      .sample(20_000);
+    let report = judge.run();
+
+    let names: Vec<(&str, &str)> = report.pairs.iter().map(|pair| (pair.a.as_str(), pair.b.as_str())).collect();
+    assert_eq!(vec![("A", "B"), ("A", "C"), ("B", "C")], names);
+
+    let a_b = Generator::new(65, 16807).zip(Generator::new(8921, 48271)).take(20_000)
+      .filter(|&(a, b)| a & 0xFF == b & 0xFF)
+      .count();
+    assert_eq!(a_b, report.pairs[0].matches);
+    for pair in &report.pairs {
+      assert_eq!(20_000.0 / 256.0, pair.expected);
+      assert!(pair.matches > 40 && pair.matches < 120);
+    }
+
+    // the sequential filtered path agrees with the batched parallel one
+    let filtered = judge.filter("C", Filter::Predicate(Box::new(|_| true))).run();
+    assert_eq!(report, filtered);
+  }
 }
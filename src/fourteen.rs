@@ -1,209 +1,424 @@
-use ten::knot_hash;
-use self::Location::*;
-
-#[derive(Debug, PartialEq, Eq, Clone)]
-enum Location {
-  Free, Unmarked, Marked(usize)
+use std::fmt;
+use std::io;
+use std::io::Write;
+use common;
+use rayon::prelude::*;
+use ten::{KnotHashParams, knot_hash_batch};
+
+/// The size of the puzzle's disk, whose columns are the 128 bits of the
+/// standard knot hash.
+const DISK_SIZE: usize = 128;
+
+const WORD_BITS: usize = 128;
+
+/// The disk's squares, with each row in as many `u128` words as it takes. The
+/// leftmost square is in the most significant bit of a row's first word, and
+/// a set bit is a used square. Bits past the last column are clear.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Disk {
+  words: Vec<u128>,
+  row_words: usize,
+  cols: usize,
 }
 
-type Disk = Vec<Vec<Location>>;
+impl Disk {
+  /// A disk of at most 128 columns, from the first `cols` bits of each row.
+  pub fn from_rows(rows: Vec<u128>, cols: usize) -> Self {
+    if cols == 0 || cols > WORD_BITS {
+      panic!("A disk from u128 rows must have between 1 and {} columns, not {}", WORD_BITS, cols);
+    }
+    Disk::from_words(rows, 1, cols)
+  }
 
-fn create_disk_for_keystring(keystring: String) -> Disk {
-  let mut disk = vec![];
+  /// A disk from the bytes of each row, one square per bit, using the first
+  /// `cols` bits of each row.
+  pub fn from_bytes<T: AsRef<[u8]>>(rows: &[T], cols: usize) -> Self {
+    if cols == 0 {
+      panic!("A disk must have at least one column");
+    }
+    let row_words = (cols + WORD_BITS - 1) / WORD_BITS;
+    let mut words = Vec::with_capacity(rows.len() * row_words);
 
-  for row_id in 0..128 {
-    let mut row_vec = vec![];
-    let hash = knot_hash(format!("{}-{}", keystring, row_id));
-    for ch in hash.chars() {
-      let bits = format!("{:04b}", ch.to_digit(16).unwrap());
-      for bit in bits.chars() {
-        if bit == '1' {
-          row_vec.push(Unmarked);
-        }
-        else {
-          row_vec.push(Free);
-        }
+    for row in rows {
+      let bytes = row.as_ref();
+      if bytes.len() * 8 < cols {
+        panic!("A row of {} bytes is too short for {} columns", bytes.len(), cols);
+      }
+      for chunk in 0..row_words {
+        let mut word = [0; 16];
+        let (from, to) = (chunk * 16, bytes.len().min(chunk * 16 + 16));
+        word[..to - from].copy_from_slice(&bytes[from..to]);
+        words.push(u128::from_be_bytes(word));
       }
     }
-    disk.push(row_vec);
+
+    Disk::from_words(words, row_words, cols)
+  }
+
+  fn from_words(mut words: Vec<u128>, row_words: usize, cols: usize) -> Self {
+    let mask = !0 << (row_words * WORD_BITS - cols);
+    for row in words.chunks_mut(row_words) {
+      row[row_words - 1] &= mask;
+    }
+    Disk { words, row_words, cols }
+  }
+
+  pub fn rows(&self) -> usize {
+    self.words.len() / self.row_words
   }
 
-  disk
+  pub fn cols(&self) -> usize {
+    self.cols
+  }
+
+  pub fn is_used(&self, row: usize, col: usize) -> bool {
+    self.words[row * self.row_words + col / WORD_BITS] >> (WORD_BITS - 1 - col % WORD_BITS) & 1 == 1
+  }
+}
+
+fn row_keys(keystring: &str, rows: usize) -> Vec<String> {
+  (0..rows).map(|row_id| format!("{}-{}", keystring, row_id)).collect()
+}
+
+/// A disk of `rows` rows, each the knot hash under `params` of the keystring
+/// and row number, with a column for every bit of the hash.
+pub fn create_disk(keystring: &str, rows: usize, params: &KnotHashParams) -> Disk {
+  let hashes: Vec<Vec<u8>> = row_keys(keystring, rows).par_iter()
+    .map(|key| params.hash(key.as_bytes()))
+    .collect();
+  Disk::from_bytes(&hashes, 8 * params.hash_len())
+}
+
+pub fn create_disk_for_keystring(keystring: String) -> Disk {
+  Disk::from_bytes(&knot_hash_batch(&row_keys(&keystring, DISK_SIZE)), DISK_SIZE)
 }
 
 fn count_used_bits_in_disk(disk: &Disk) -> usize {
-  disk.iter()
-  .map(|row|
-    row.iter()
-    .fold(0, |b, l| match *l {
-      Free => b,
-      _ => b + 1
-    }))
-  .sum()
+  disk.words.iter().map(|word| word.count_ones() as usize).sum()
 }
 
-fn unmarked_location(disk: &Disk) -> Option<(usize, usize)> {
-  let mut row = 0;
+/// Disjoint sets of provisional labels, where each set's root is its smallest
+/// label.
+struct UnionFind {
+  parent: Vec<usize>,
+}
 
-  while row < disk.len() {
-    let mut col = 0;
+impl UnionFind {
+  fn add(&mut self) -> usize {
+    self.parent.push(self.parent.len());
+    self.parent.len() - 1
+  }
 
-    while col < disk[row].len() {
+  fn find(&mut self, mut label: usize) -> usize {
+    while self.parent[label] != label {
+      // path halving: point every other label on the way at its grandparent
+      self.parent[label] = self.parent[self.parent[label]];
+      label = self.parent[label];
+    }
+    label
+  }
 
-      match disk[row][col] {
-        Unmarked => {
-          return Some((row, col));
-        }
-        _ => {}
+  fn union(&mut self, a: usize, b: usize) -> usize {
+    let (a, b) = (self.find(a), self.find(b));
+    let (root, child) = if a < b { (a, b) } else { (b, a) };
+    self.parent[child] = root;
+    root
+  }
+}
+
+/// A connected group of used squares, with the rows and columns of its
+/// bounding box, inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+  pub id: usize,
+  pub size: usize,
+  pub top: usize,
+  pub left: usize,
+  pub bottom: usize,
+  pub right: usize,
+}
+
+/// Which squares count as adjacent when finding regions. The puzzle only
+/// joins squares that share an edge, and stops at the edges of the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Adjacency {
+  /// Also join squares that only share a corner.
+  pub diagonal: bool,
+  /// Treat the disk as a torus, so the last row and column are next to the
+  /// first.
+  pub wrap: bool,
+}
+
+const EDGE_OFFSETS: [(isize, isize); 4] = [(-1, 0), (0, -1), (0, 1), (1, 0)];
+const CORNER_OFFSETS: [(isize, isize); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+
+impl Adjacency {
+  /// The squares adjacent to `(row, col)` on a disk of the given size.
+  fn neighbours(&self, (row, col): (usize, usize), (rows, cols): (usize, usize)) -> impl Iterator<Item=(usize, usize)> {
+    let corners: &[(isize, isize)] = if self.diagonal { &CORNER_OFFSETS } else { &[] };
+    let wrap = self.wrap;
+    let step = move |pos: usize, delta: isize, len: usize| {
+      let moved = pos as isize + delta;
+      if moved >= 0 && moved < len as isize {
+        Some(moved as usize)
+      }
+      else if wrap {
+        Some((moved + len as isize) as usize % len)
+      }
+      else {
+        None
       }
+    };
+
+    EDGE_OFFSETS.iter().chain(corners)
+    .filter_map(move |&(dr, dc)| match (step(row, dr, rows), step(col, dc, cols)) {
+      (Some(r), Some(c)) => Some((r, c)),
+      _ => None,
+    })
+  }
+}
+
+const NO_REGION: usize = usize::MAX;
+
+/// The region each used square of a disk belongs to. Region ids count up from
+/// 0 in the order their first square appears, reading row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionMap {
+  cols: usize,
+  labels: Vec<usize>,
+  regions: Vec<Region>,
+}
+
+impl RegionMap {
+  pub fn region_at(&self, row: usize, col: usize) -> Option<usize> {
+    match self.labels[row * self.cols + col] {
+      NO_REGION => None,
+      id => Some(id),
+    }
+  }
+
+  pub fn regions(&self) -> &[Region] {
+    &self.regions
+  }
+
+  fn rows(&self) -> usize {
+    self.labels.len() / self.cols
+  }
+
+  /// Writes the map as a binary PPM image, with each square drawn as a
+  /// `scale` by `scale` square. Free squares are black, and each region gets
+  /// a colour of its own.
+  pub fn write_ppm<W: Write>(&self, out: &mut W, scale: usize) -> io::Result<()> {
+    common::write_ppm(out, self.cols, self.rows(), scale, |x, y| match self.labels[y * self.cols + x] {
+      NO_REGION => [0, 0, 0],
+      label => region_color(label),
+    })
+  }
 
-      col += 1;
+  /// Draws the map with each used square showing its region id, and `.` for
+  /// free squares, all padded to the width of the largest id.
+  pub fn to_ascii(&self) -> String {
+    let width = self.regions.len().saturating_sub(1).to_string().len();
+    let mut out = String::new();
+
+    for row in self.labels.chunks(self.cols) {
+      let squares: Vec<String> = row.iter().map(|&label| match label {
+        NO_REGION => format!("{:>w$}", ".", w = width),
+        id => format!("{:>w$}", id, w = width),
+      }).collect();
+      out.push_str(&squares.join(" "));
+      out.push('\n');
     }
 
-    row += 1;
+    out
   }
 
-  None
+  pub fn stats(&self) -> RegionStats {
+    let (rows, cols) = (self.rows(), self.cols);
+    let mut histogram = vec![];
+    for region in &self.regions {
+      let bucket = (0usize.leading_zeros() - region.size.leading_zeros() - 1) as usize;
+      if histogram.len() <= bucket {
+        histogram.resize(bucket + 1, 0);
+      }
+      histogram[bucket] += 1;
+    }
+
+    RegionStats {
+      regions: self.regions.len(),
+      used: self.regions.iter().map(|region| region.size).sum(),
+      histogram,
+      largest: self.regions.iter().max_by_key(|region| (region.size, usize::MAX - region.id)).cloned(),
+      touching_border: self.regions.iter()
+        .filter(|region| region.top == 0 || region.left == 0 || region.bottom == rows - 1 || region.right == cols - 1)
+        .count(),
+    }
+  }
 }
 
-fn mark_region(disk: &mut Disk, (row, col): (usize, usize), region_id: usize) {
-  if row < disk.len() && col < disk[row].len() && disk[row][col] == Unmarked {
-    disk[row][col] = Marked(region_id);
+/// A bright colour for a region, spreading consecutive ids across the
+/// spectrum.
+fn region_color(id: usize) -> [u8; 3] {
+  let hash = (id as u32 + 1).wrapping_mul(2_654_435_761);
+  [(hash >> 24) as u8 | 0x40, (hash >> 16) as u8 | 0x40, (hash >> 8) as u8 | 0x40]
+}
 
-    mark_region(disk, (row, col + 1), region_id);
-    mark_region(disk, (row + 1, col), region_id);
+/// A summary of a disk's regions, for comparing how fragmented disks are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionStats {
+  pub regions: usize,
+  pub used: usize,
+  /// Number of regions by size, where entry `k` counts the regions of size
+  /// `2^k` up to `2^(k+1) - 1`.
+  pub histogram: Vec<usize>,
+  /// The largest region, or the first of them if more than one is largest.
+  pub largest: Option<Region>,
+  pub touching_border: usize,
+}
 
-    if col > 0 {
-      mark_region(disk, (row, col - 1), region_id);
+impl fmt::Display for RegionStats {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    writeln!(f, "Regions:           {}", self.regions)?;
+    writeln!(f, "Used squares:      {}", self.used)?;
+    if self.regions > 0 {
+      writeln!(f, "Mean region size:  {:.2}", self.used as f64 / self.regions as f64)?;
     }
-    if row > 0 {
-      mark_region(disk, (row - 1, col), region_id);
+    if let Some(ref largest) = self.largest {
+      writeln!(f, "Largest region:    #{}, {} squares in rows {}-{}, columns {}-{}",
+        largest.id, largest.size, largest.top, largest.bottom, largest.left, largest.right)?;
     }
+    writeln!(f, "Touching border:   {}", self.touching_border)?;
+    writeln!(f, "Sizes:")?;
+    for (bucket, &count) in self.histogram.iter().enumerate() {
+      let (low, high) = (1 << bucket, (1 << (bucket + 1)) - 1);
+      let sizes = if low == high { low.to_string() } else { format!("{}-{}", low, high) };
+      writeln!(f, "  {:>9}  {:>5}  {}", sizes, count, "#".repeat((count + 9) / 10))?;
+    }
+    Ok(())
   }
 }
 
-fn count_regions_in_disk(disk: &mut Disk) -> usize {
-  let mut regions = 0;
+/// Labels the regions of a disk in one pass over its squares, joining each
+/// used square to the adjacent used squares that come before it, then numbers
+/// the joined sets. With `wrap`, a square in the first row or column is joined
+/// to ones at the other end when the scan reaches them.
+pub fn label_regions(disk: &Disk, adjacency: Adjacency) -> RegionMap {
+  let (rows, cols) = (disk.rows(), disk.cols());
+  let mut labels = vec![NO_REGION; rows * cols];
+  let mut sets = UnionFind { parent: vec![] };
+
+  for row in 0..rows {
+    for col in 0..cols {
+      if !disk.is_used(row, col) {
+        continue;
+      }
+
+      let index = row * cols + col;
+      let mut label = NO_REGION;
+      for (r, c) in adjacency.neighbours((row, col), (rows, cols)) {
+        let neighbour = labels[r * cols + c];
+        if r * cols + c >= index || neighbour == NO_REGION {
+          continue;
+        }
+        label = if label == NO_REGION { neighbour } else { sets.union(label, neighbour) };
+      }
+      labels[index] = if label == NO_REGION { sets.add() } else { label };
+    }
+  }
+
+  let mut ids = vec![NO_REGION; sets.parent.len()];
+  let mut regions: Vec<Region> = vec![];
+  for row in 0..rows {
+    for col in 0..cols {
+      let label = labels[row * cols + col];
+      if label == NO_REGION {
+        continue;
+      }
 
-  while let Some(location) = unmarked_location(&disk) {
-    regions += 1;
-    mark_region(disk, location, 2 + regions);
+      let root = sets.find(label);
+      if ids[root] == NO_REGION {
+        ids[root] = regions.len();
+        regions.push(Region { id: regions.len(), size: 0, top: row, left: col, bottom: row, right: col });
+      }
+
+      let region = &mut regions[ids[root]];
+      region.size += 1;
+      region.left = region.left.min(col);
+      region.bottom = row;
+      region.right = region.right.max(col);
+      labels[row * cols + col] = region.id;
+    }
   }
 
-  regions
+  RegionMap { cols, labels, regions }
+}
+
+pub fn count_regions_in_disk(disk: &Disk, adjacency: Adjacency) -> usize {
+  label_regions(disk, adjacency).regions().len()
 }
 
 pub fn main() {
-  let mut disk = create_disk_for_keystring("nbysizxe".to_string());
+  let disk = create_disk_for_keystring("nbysizxe".to_string());
 
   let bits = count_used_bits_in_disk(&disk);
   println!("Used bits = {:?}", bits);
 
-  let regions = count_regions_in_disk(&mut disk);
+  let regions = count_regions_in_disk(&disk, Adjacency::default());
   println!("Regions = {:?}", regions);
 }
 
 #[cfg(test)]
 mod test {
   use super::*;
+  use ten::to_bits;
 
-  #[test]
-  fn unmarked_location_works() {
-    let mut disk1 = vec![vec![Free; 128]; 128];
-    disk1[3][3] = Unmarked;
-    disk1[4][4] = Unmarked;
-
-    let mut disk2 = vec![vec![Free; 128]; 128];
-    disk2[3][3] = Marked(2);
-    disk2[4][4] = Unmarked;
+  /// A 128-row disk with the given squares used.
+  fn disk_with(squares: &[(usize, usize)]) -> Disk {
+    let mut rows = vec![0u128; DISK_SIZE];
+    for &(row, col) in squares {
+      rows[row] |= 1 << (DISK_SIZE - 1 - col);
+    }
+    Disk::from_rows(rows, DISK_SIZE)
+  }
 
-    assert_eq!(Some((3,3)), unmarked_location(&disk1));
-    assert_eq!(Some((4,4)), unmarked_location(&disk2));
+  #[test]
+  fn label_regions_works() {
+    let disk = disk_with(&[
+      (0, 0), (0, 1), (1, 0), (1, 1),
+      (2, 2), (2, 3),
+      (5, 6), (5, 7), (5, 8), (5, 9), (6, 8), (7, 8), (7, 7), (7, 6), (7, 5),
+    ]);
+    let map = label_regions(&disk, Adjacency::default());
+
+    assert_eq!(&[
+      Region { id: 0, size: 4, top: 0, left: 0, bottom: 1, right: 1 },
+      Region { id: 1, size: 2, top: 2, left: 2, bottom: 2, right: 3 },
+      Region { id: 2, size: 9, top: 5, left: 5, bottom: 7, right: 9 },
+    ], map.regions());
+    assert_eq!(Some(0), map.region_at(1, 1));
+    assert_eq!(Some(2), map.region_at(7, 5));
+    assert_eq!(None, map.region_at(6, 7));
   }
 
   #[test]
-  fn mark_region_works() {
-    use super::Location::*;
-    let mut disk = vec![vec![Free; 128]; 128];
-    disk[0][0] = Unmarked;
-    disk[0][1] = Unmarked;
-    disk[1][0] = Unmarked;
-    disk[1][1] = Unmarked;
-    disk[2][2] = Unmarked;
-    disk[2][3] = Unmarked;
-
-    disk[5][6] = Unmarked;
-    disk[5][7] = Unmarked;
-    disk[5][8] = Unmarked;
-    disk[5][9] = Unmarked;
-    disk[6][8] = Unmarked;
-    disk[7][8] = Unmarked;
-    disk[7][7] = Unmarked;
-    disk[7][6] = Unmarked;
-    disk[7][5] = Unmarked;
-
-    mark_region(&mut disk, (0, 0), 3);
-
-    assert_eq!(Marked(3), disk[0][0]);
-    assert_eq!(Marked(3), disk[0][1]);
-    assert_eq!(Marked(3), disk[1][0]);
-    assert_eq!(Marked(3), disk[1][1]);
-    assert_eq!(Unmarked, disk[2][2]);
-    assert_eq!(Unmarked, disk[2][3]);
-    assert_eq!(Unmarked, disk[5][6]);
-    assert_eq!(Unmarked, disk[5][7]);
-    assert_eq!(Unmarked, disk[5][8]);
-    assert_eq!(Unmarked, disk[5][9]);
-    assert_eq!(Unmarked, disk[6][8]);
-    assert_eq!(Unmarked, disk[7][8]);
-    assert_eq!(Unmarked, disk[7][7]);
-    assert_eq!(Unmarked, disk[7][6]);
-    assert_eq!(Unmarked, disk[7][5]);
-
-    mark_region(&mut disk, (2, 2), 4);
-
-    assert_eq!(Marked(3), disk[0][0]);
-    assert_eq!(Marked(3), disk[0][1]);
-    assert_eq!(Marked(3), disk[1][0]);
-    assert_eq!(Marked(3), disk[1][1]);
-    assert_eq!(Marked(4), disk[2][2]);
-    assert_eq!(Marked(4), disk[2][3]);
-    assert_eq!(Unmarked, disk[5][6]);
-    assert_eq!(Unmarked, disk[5][7]);
-    assert_eq!(Unmarked, disk[5][8]);
-    assert_eq!(Unmarked, disk[5][9]);
-    assert_eq!(Unmarked, disk[6][8]);
-    assert_eq!(Unmarked, disk[7][8]);
-    assert_eq!(Unmarked, disk[7][7]);
-    assert_eq!(Unmarked, disk[7][6]);
-    assert_eq!(Unmarked, disk[7][5]);
-
-    mark_region(&mut disk, (5,6), 22);
-
-    assert_eq!(Marked(3), disk[0][0]);
-    assert_eq!(Marked(3), disk[0][1]);
-    assert_eq!(Marked(3), disk[1][0]);
-    assert_eq!(Marked(3), disk[1][1]);
-    assert_eq!(Marked(4), disk[2][2]);
-    assert_eq!(Marked(4), disk[2][3]);
-    assert_eq!(Marked(22), disk[5][6]);
-    assert_eq!(Marked(22), disk[5][7]);
-    assert_eq!(Marked(22), disk[5][8]);
-    assert_eq!(Marked(22), disk[5][9]);
-    assert_eq!(Marked(22), disk[6][8]);
-    assert_eq!(Marked(22), disk[7][8]);
-    assert_eq!(Marked(22), disk[7][7]);
-    assert_eq!(Marked(22), disk[7][6]);
-    assert_eq!(Marked(22), disk[7][5]);
+  fn label_regions_joins_regions_that_meet_later() {
+    // a U shape, whose arms get separate labels until the bottom row
+    let disk = disk_with(&[(0, 0), (0, 4), (1, 0), (1, 4), (2, 0), (2, 1), (2, 2), (2, 3), (2, 4), (0, 6)]);
+    let map = label_regions(&disk, Adjacency::default());
+
+    assert_eq!(2, map.regions().len());
+    assert_eq!(Region { id: 0, size: 9, top: 0, left: 0, bottom: 2, right: 4 }, map.regions()[0]);
+    assert_eq!(Some(0), map.region_at(0, 4));
+    assert_eq!(Some(1), map.region_at(0, 6));
   }
 
   #[test]
   fn create_disk_for_keystring_works() {
     let disk = create_disk_for_keystring("flqrgnkx".to_string());
-    assert_eq!(128, disk.len());
-    assert_eq!(128, disk[0].len());
-    assert_eq!(Unmarked, disk[0][0]);
-    assert_eq!(Free, disk[5][2]);
+    assert_eq!(128, disk.rows());
+    assert_eq!(128, disk.cols());
+    assert!(disk.is_used(0, 0));
+    assert!(!disk.is_used(5, 2));
   }
 
   #[test]
@@ -214,17 +429,120 @@ mod test {
 
   #[test]
   fn count_regions_in_disk_works() {
-    let mut disk = create_disk_for_keystring("flqrgnkx".to_string());
-    assert_eq!(1242, count_regions_in_disk(&mut disk));
-
-    let mut disk2 = vec![vec![Free; 128]; 128];
-    disk2[0][0] = Unmarked;
-    disk2[0][1] = Unmarked;
-    disk2[1][0] = Unmarked;
-    disk2[1][1] = Unmarked;
-    disk2[2][2] = Unmarked;
-    disk2[2][3] = Unmarked;
-
-    assert_eq!(2, count_regions_in_disk(&mut disk2));
+    let disk = create_disk_for_keystring("flqrgnkx".to_string());
+    assert_eq!(1242, count_regions_in_disk(&disk, Adjacency::default()));
+
+    let disk2 = disk_with(&[(0, 0), (0, 1), (1, 0), (1, 1), (2, 2), (2, 3)]);
+    assert_eq!(2, count_regions_in_disk(&disk2, Adjacency::default()));
+  }
+
+  #[test]
+  fn create_disk_takes_its_width_from_the_hash() {
+    let disk = create_disk("flqrgnkx", DISK_SIZE, &KnotHashParams::new());
+    assert_eq!(create_disk_for_keystring("flqrgnkx".to_string()), disk);
+
+    for &(block_size, cols) in &[(32, 64), (8, 256), (3, 688), (1, 2048)] {
+      let params = KnotHashParams::new().block_size(block_size);
+      let disk = create_disk("flqrgnkx", 20, &params);
+      assert_eq!((20, cols), (disk.rows(), disk.cols()));
+
+      for row in 0..20 {
+        let bits = to_bits(&params.hash(format!("flqrgnkx-{}", row).as_bytes()));
+        let squares: Vec<bool> = (0..cols).map(|col| disk.is_used(row, col)).collect();
+        assert_eq!(bits, squares);
+      }
+    }
+  }
+
+  #[test]
+  fn wide_disks_label_regions_across_words() {
+    let mut rows = vec![vec![0u8; 32]; 3];
+    // a region crossing from the first word of each row into the second
+    rows[0][15] = 0b0000_0001;
+    rows[0][16] = 0b1000_0000;
+    rows[1][16] = 0b1000_0000;
+    rows[2][31] = 0b0000_0001;
+    let disk = Disk::from_bytes(&rows, 256);
+    assert!(disk.is_used(0, 127) && disk.is_used(0, 128) && disk.is_used(2, 255));
+    assert_eq!(4, count_used_bits_in_disk(&disk));
+
+    let map = label_regions(&disk, Adjacency::default());
+    assert_eq!(&[
+      Region { id: 0, size: 3, top: 0, left: 127, bottom: 1, right: 128 },
+      Region { id: 1, size: 1, top: 2, left: 255, bottom: 2, right: 255 },
+    ], map.regions());
+
+    // squares past the last column are dropped
+    let narrow = Disk::from_bytes(&rows, 250);
+    assert_eq!(3, count_used_bits_in_disk(&narrow));
+    assert_eq!(1, count_regions_in_disk(&narrow, Adjacency::default()));
+  }
+
+  #[test]
+  fn diagonal_adjacency_joins_corners() {
+    let disk = disk_with(&[(0, 0), (1, 1), (2, 0), (2, 2), (0, 5)]);
+    assert_eq!(5, count_regions_in_disk(&disk, Adjacency::default()));
+
+    let diagonal = Adjacency { diagonal: true, wrap: false };
+    let map = label_regions(&disk, diagonal);
+    assert_eq!(2, map.regions().len());
+    assert_eq!(Region { id: 0, size: 4, top: 0, left: 0, bottom: 2, right: 2 }, map.regions()[0]);
+  }
+
+  #[test]
+  fn wrapping_adjacency_joins_opposite_edges() {
+    let disk = Disk::from_rows(vec![1 << 127 | 1 << 124, 0, 1 << 124, 1 << 126], 4);
+    assert_eq!(4, count_regions_in_disk(&disk, Adjacency::default()));
+
+    // the two squares in row 0 meet round the side, and row 0 meets row 3
+    // round the top and bottom, but only diagonally
+    assert_eq!(3, count_regions_in_disk(&disk, Adjacency { diagonal: false, wrap: true }));
+    assert_eq!(2, count_regions_in_disk(&disk, Adjacency { diagonal: true, wrap: true }));
+
+    // a region that first meets itself on the far side keeps the first id
+    let map = label_regions(&disk, Adjacency { diagonal: true, wrap: true });
+    assert_eq!(Some(0), map.region_at(3, 1));
+  }
+
+  #[test]
+  fn region_map_draws_ascii_ids() {
+    let disk = Disk::from_rows(vec![0b1100 << 124, 0b0101 << 124], 4);
+    let map = label_regions(&disk, Adjacency::default());
+    assert_eq!("0 0 . .\n. 0 . 1\n", map.to_ascii());
+  }
+
+  #[test]
+  fn region_map_writes_ppm() {
+    let disk = Disk::from_rows(vec![0b1000 << 124, 0b0001 << 124], 4);
+    let map = label_regions(&disk, Adjacency::default());
+
+    let mut ppm = vec![];
+    map.write_ppm(&mut ppm, 3).unwrap();
+    let header = b"P6\n12 6\n255\n";
+    assert_eq!(&header[..], &ppm[..header.len()]);
+    assert_eq!(header.len() + 12 * 6 * 3, ppm.len());
+
+    let pixel = |x: usize, y: usize| &ppm[header.len() + (y * 12 + x) * 3..][..3];
+    assert_eq!(&region_color(0)[..], pixel(2, 2));
+    assert_eq!(&[0, 0, 0][..], pixel(3, 0));
+    assert_eq!(&region_color(1)[..], pixel(11, 5));
+    assert_ne!(region_color(0), region_color(1));
+  }
+
+  #[test]
+  fn region_stats_work() {
+    let disk = disk_with(&[
+      (0, 0), (0, 1), (1, 0), (1, 1),
+      (2, 3),
+      (5, 6), (5, 7), (5, 8), (5, 9), (6, 8), (7, 8), (7, 7), (7, 6), (7, 5),
+      (127, 50),
+    ]);
+    let stats = label_regions(&disk, Adjacency::default()).stats();
+
+    assert_eq!(4, stats.regions);
+    assert_eq!(15, stats.used);
+    assert_eq!(vec![2, 0, 1, 1], stats.histogram);
+    assert_eq!(Some(Region { id: 2, size: 9, top: 5, left: 5, bottom: 7, right: 9 }), stats.largest);
+    assert_eq!(2, stats.touching_border);
   }
 }
@@ -0,0 +1,13 @@
+extern crate rayon;
+
+pub mod common;
+pub mod one;
+pub mod six;
+pub mod ten;
+pub mod fourteen;
+pub mod fifteen;
+pub mod sixteen;
+pub mod seventeen;
+pub mod eighteen;
+pub mod nineteen;
+pub mod twentyfive;
@@ -1,5 +1,33 @@
-mod twentyfive;
+extern crate adventofcode;
+
+use std::env;
+use adventofcode::{one, six, ten, fourteen, fifteen, sixteen, seventeen, eighteen, nineteen, twentyfive};
+
+/// The day followed by its input file, which defaults to the one in `input`.
+fn input_args(default: &str) -> Vec<String> {
+  let mut args: Vec<String> = env::args().skip(1).collect();
+  if args.len() < 2 {
+    args.push(default.to_string());
+  }
+  args
+}
 
 fn main() {
-  twentyfive::main();
+  match env::args().nth(1).as_deref() {
+    Some("1") => {
+      let args = input_args("input/one.txt");
+      one::main(args.clone());
+      one::main_plus(args);
+    },
+    Some("6") => six::main(input_args("input/six.txt")),
+    Some("10") => ten::main(),
+    Some("14") => fourteen::main(),
+    Some("15") => fifteen::main(),
+    Some("16") => sixteen::main(),
+    Some("17") => seventeen::main(),
+    Some("18") => eighteen::main(),
+    Some("19") => nineteen::main(),
+    Some("25") | None => twentyfive::main(),
+    Some(day) => panic!("No solution for day {}", day),
+  }
 }
@@ -1,18 +1,79 @@
+use std::collections::HashMap;
+use std::fmt;
 use self::Dir::*;
 
-#[derive(Debug, PartialEq)]
-enum Dir {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dir {
   Up, Down, Left, Right
 }
 
+impl Dir {
+  fn is_vertical(self) -> bool {
+    self == Up || self == Down
+  }
+
+  /// The two ways a tube can turn at a junction when heading this way.
+  fn turns(self) -> [Dir; 2] {
+    if self.is_vertical() { [Left, Right] } else { [Up, Down] }
+  }
+}
+
 type Map = Vec<Vec<char>>;
 
-fn initial_column(map: &Map) -> usize {
-  map[0].iter().position(|&x|x == '|').unwrap()
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Position {
+  pub row: usize,
+  pub col: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteError {
+  NoEntry,
+  InvalidTile { row: usize, col: usize, tile: char },
+  Ambiguous { row: usize, col: usize, exits: Vec<Dir> },
+  DeadEnd { row: usize, col: usize },
+  Loop { row: usize, col: usize },
+}
+
+impl fmt::Display for RouteError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      RouteError::NoEntry => write!(f, "No tube enters the diagram from an edge"),
+      RouteError::InvalidTile { row, col, tile } => write!(f, "Invalid tile {:?} at ({},{})", tile, row, col),
+      RouteError::Ambiguous { row, col, ref exits } => write!(f, "Junction at ({},{}) could lead {:?}", row, col, exits),
+      RouteError::DeadEnd { row, col } => write!(f, "Junction at ({},{}) has no way out", row, col),
+      RouteError::Loop { row, col } => write!(f, "Route loops back to ({},{})", row, col),
+    }
+  }
+}
+
+/// A straight run of tube, from its first tile up to the junction where it
+/// has to turn or the tile where it stops.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+  pub start: Position,
+  pub heading: Dir,
+  pub end: Position,
+  pub letters: String,
+  pub steps: usize,
+  pub junction: bool,
 }
 
-fn directions() -> Vec<Dir> {
-  vec![Up, Down, Left, Right]
+/// The letters and number of steps along a route from an entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+  pub entry: Position,
+  pub end: Position,
+  pub letters: String,
+  pub steps: usize,
+}
+
+/// A routing diagram as segments joined at junctions. Segments are keyed by
+/// their first tile and heading, since a crossing tile belongs to two.
+pub struct Network {
+  map: Map,
+  entries: Vec<(Position, Dir)>,
+  segments: HashMap<(Position, Dir), Segment>,
 }
 
 fn check_bounds(map: &Map, row: usize, col: usize, dir: &Dir) -> bool {
@@ -24,170 +85,330 @@ fn check_bounds(map: &Map, row: usize, col: usize, dir: &Dir) -> bool {
   }
 }
 
-fn rotate(map: &Map, direction: Dir, row: usize, col: usize) -> Dir {
-  let excluded = match direction {
-    Up => Down,
-    Down => Up,
-    Left => Right,
-    Right => Left
-  };
-  for d in directions() {
-    let d_leads_to_pipe_or_alpha = match d {
-      Up => if check_bounds(map, row, col, &Up) {
-          map[row-1][col].is_alphabetic() || map[row-1][col] == '|'
-        }
-        else {
-          false
-        },
-      Down => if check_bounds(map, row, col, &Down) {
-          map[row+1][col].is_alphabetic() || map[row+1][col] == '|'
-        }
-        else {
-          false
-        },
-      Left => if check_bounds(map, row, col, &Left) {
-          map[row][col-1].is_alphabetic() || map[row][col-1] == '-'
+fn step(map: &Map, pos: Position, dir: Dir) -> Option<Position> {
+  if !check_bounds(map, pos.row, pos.col, &dir) {
+    return None;
+  }
+  Some(match dir {
+    Up => Position { row: pos.row - 1, col: pos.col },
+    Down => Position { row: pos.row + 1, col: pos.col },
+    Left => Position { row: pos.row, col: pos.col - 1 },
+    Right => Position { row: pos.row, col: pos.col + 1 },
+  })
+}
+
+fn tile(map: &Map, pos: Position) -> char {
+  map[pos.row][pos.col]
+}
+
+/// Whether `tile` can carry a tube running along `dir`.
+fn fits(tile: char, dir: Dir) -> bool {
+  tile.is_alphabetic() || tile == '+' || tile == if dir.is_vertical() { '|' } else { '-' }
+}
+
+/// The ways out of the junction at `pos` when arriving heading `heading`.
+fn exits(map: &Map, heading: Dir, pos: Position) -> Vec<Dir> {
+  heading.turns().iter()
+  .cloned()
+  .filter(|&d| step(map, pos, d).map_or(false, |next| fits(tile(map, next), d)))
+  .collect()
+}
+
+fn walk(map: &Map, start: Position, heading: Dir) -> Segment {
+  let mut segment = Segment { start, heading, end: start, letters: String::new(), steps: 0, junction: false };
+  let mut pos = start;
+
+  loop {
+    let t = tile(map, pos);
+    segment.end = pos;
+    segment.steps += 1;
+    if t.is_alphabetic() {
+      segment.letters.push(t);
+    }
+    if t == '+' {
+      segment.junction = true;
+      return segment;
+    }
+
+    match step(map, pos, heading) {
+      Some(next) if tile(map, next) != ' ' => pos = next,
+      _ => return segment,
+    }
+  }
+}
+
+/// The tiles on the edges of the map where a tube comes in, in reading order.
+/// A letter on an edge only counts if the tube carries on inwards from it.
+fn find_entries(map: &Map) -> Vec<(Position, Dir)> {
+  let (rows, cols) = (map.len(), map[0].len());
+  let mut entries = Vec::new();
+
+  for row in 0..rows {
+    for col in 0..cols {
+      let pos = Position { row, col };
+      let t = tile(map, pos);
+      let inwards = [(row == 0, Down), (row == rows - 1, Up), (col == 0, Right), (col == cols - 1, Left)];
+      for &(on_edge, dir) in &inwards {
+        if !on_edge || t == '+' || !fits(t, dir) {
+          continue;
         }
-        else {
-          false
-        },
-      Right => if check_bounds(map, row, col, &Right) {
-          map[row][col+1].is_alphabetic() || map[row][col+1] == '-'
+        let leads_in = step(map, pos, dir).map_or(false, |next| tile(map, next) != ' ');
+        if !t.is_alphabetic() || leads_in {
+          entries.push((pos, dir));
         }
-        else {
-          false
-        },
-    };
-    if d != excluded && d_leads_to_pipe_or_alpha {
-      return d;
+      }
     }
   }
-  panic!(format!("Couldn't find a rotation at ({},{}), coming from {:?}", row, col, direction));
+
+  entries
 }
 
-fn navigate(map: &Map) -> (String, usize) {
-  let mut symbols = String::new();
-  let mut d = Down;
-  let mut row = 0;
-  let mut col = initial_column(map);
-  let mut steps = 1;
+impl Network {
+  pub fn parse(input: &str) -> Result<Network, RouteError> {
+    let lines: Vec<&str> = input.lines().collect();
+    let width = lines.iter().map(|ln| ln.chars().count()).max().unwrap_or(0);
+    if width == 0 {
+      return Err(RouteError::NoEntry);
+    }
 
-  loop {
-    if check_bounds(map, row, col, &d) {
-      // take a step in the current direction
-      match d {
-        Up => row -= 1,
-        Down => row += 1,
-        Left => col -= 1,
-        Right => col += 1,
+    let mut map: Map = Vec::new();
+    for (row, ln) in lines.iter().enumerate() {
+      let mut tiles: Vec<char> = ln.chars().collect();
+      if let Some(col) = tiles.iter().position(|&t| !(t == ' ' || t == '|' || t == '-' || t == '+' || t.is_alphabetic())) {
+        return Err(RouteError::InvalidTile { row, col, tile: tiles[col] });
       }
+      tiles.resize(width, ' ');
+      map.push(tiles);
+    }
 
-      // if we landed on a tile w/ a letter, collect it.
-      let tile = map[row][col];
-      if tile.is_alphabetic() {
-        symbols.push(tile);
-      }
+    let entries = find_entries(&map);
+    if entries.is_empty() {
+      return Err(RouteError::NoEntry);
+    }
 
-      // if we landed on a junction, determine the next direaction
-      if tile == '+' {
-        d = rotate(map, d, row, col);
+    let mut segments = HashMap::new();
+    let mut pending = entries.clone();
+    while let Some(key) = pending.pop() {
+      if segments.contains_key(&key) {
+        continue;
+      }
+      let segment = walk(&map, key.0, key.1);
+      if segment.junction {
+        for d in exits(&map, segment.heading, segment.end) {
+          pending.push((step(&map, segment.end, d).unwrap(), d));
+        }
       }
+      segments.insert(key, segment);
+    }
+
+    Ok(Network { map, entries, segments })
+  }
 
-      // if we landed on blank, we're done
-      if tile == ' ' {
-        break;
+  pub fn entries(&self) -> &[(Position, Dir)] {
+    &self.entries
+  }
+
+  pub fn segments(&self) -> impl Iterator<Item = &Segment> {
+    self.segments.values()
+  }
+
+  /// Where to go after `segment`, or `None` if the route stops there.
+  fn next(&self, segment: &Segment) -> Result<Option<(Position, Dir)>, RouteError> {
+    if !segment.junction {
+      return Ok(None);
+    }
+
+    let (row, col) = (segment.end.row, segment.end.col);
+    let exits = exits(&self.map, segment.heading, segment.end);
+    match exits.len() {
+      0 => Err(RouteError::DeadEnd { row, col }),
+      1 => Ok(Some((step(&self.map, segment.end, exits[0]).unwrap(), exits[0]))),
+      _ => Err(RouteError::Ambiguous { row, col, exits }),
+    }
+  }
+
+  fn follow(&self, entry: (Position, Dir)) -> Result<Route, RouteError> {
+    let mut route = Route { entry: entry.0, end: entry.0, letters: String::new(), steps: 0 };
+    let mut seen = Vec::new();
+    let mut key = entry;
+
+    loop {
+      if seen.contains(&key) {
+        return Err(RouteError::Loop { row: key.0.row, col: key.0.col });
       }
-      else {
-        steps += 1;
+      seen.push(key);
+
+      let segment = &self.segments[&key];
+      route.letters.push_str(&segment.letters);
+      route.steps += segment.steps;
+      route.end = segment.end;
+
+      match self.next(segment)? {
+        Some(next) => key = next,
+        None => return Ok(route),
       }
     }
-    // current dir leads to out of bounds
-    else {
-      break;
+  }
+
+  /// The route from each entry, or why it can't be followed.
+  pub fn routes(&self) -> Vec<Result<Route, RouteError>> {
+    self.entries.iter().map(|&entry| self.follow(entry)).collect()
+  }
+
+  /// The route from the first entry in reading order, as in the puzzle.
+  pub fn route(&self) -> Result<Route, RouteError> {
+    self.follow(self.entries[0])
+  }
+
+  /// Every path from every entry, taking each way out of an ambiguous
+  /// junction in turn. A path stops at a dead end or where it would loop.
+  pub fn paths(&self) -> Vec<Route> {
+    let mut paths = Vec::new();
+    for &entry in &self.entries {
+      let route = Route { entry: entry.0, end: entry.0, letters: String::new(), steps: 0 };
+      self.branch(entry, route, &mut Vec::new(), &mut paths);
     }
+    paths
   }
 
-  (symbols, steps)
+  fn branch(&self, key: (Position, Dir), mut route: Route, seen: &mut Vec<(Position, Dir)>, paths: &mut Vec<Route>) {
+    if seen.contains(&key) {
+      paths.push(route);
+      return;
+    }
+
+    let segment = &self.segments[&key];
+    route.letters.push_str(&segment.letters);
+    route.steps += segment.steps;
+    route.end = segment.end;
+
+    let exits = if segment.junction { exits(&self.map, segment.heading, segment.end) } else { Vec::new() };
+    if exits.is_empty() {
+      paths.push(route);
+      return;
+    }
+
+    seen.push(key);
+    for d in exits {
+      self.branch((step(&self.map, segment.end, d).unwrap(), d), route.clone(), seen, paths);
+    }
+    seen.pop();
+  }
 }
 
 pub fn main () {
-  let input: Vec<Vec<char>> = include_str!("../input/nineteen").lines().map(|ln|ln.chars().collect()).collect();
-  let path = navigate(&input);
-  println!("Symbols = {}", path.0);
-  println!("Steps = {}", path.1);
+  let network = match Network::parse(include_str!("../input/nineteen")) {
+    Ok(network) => network,
+    Err(e) => panic!("Couldn't read the routing diagram: {}", e),
+  };
+  match network.route() {
+    Ok(route) => {
+      println!("Symbols = {}", route.letters);
+      println!("Steps = {}", route.steps);
+    },
+    Err(e) => panic!("{}", e),
+  }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
 
+  const EXAMPLE: &str = "     |          \n     |  +--+    \n     A  |  C    \n F---|----E|--+ \n     |  |  |  D \n     +B-+  +--+ \n";
+
+  fn map(lines: &[&str]) -> Map {
+    lines.iter().map(|ln| ln.chars().collect()).collect()
+  }
+
+  fn at(row: usize, col: usize) -> Position {
+    Position { row, col }
+  }
+
   #[test]
   fn check_bounds_works() {
-    let map = vec![
-      "     |          ".chars().collect(),
-      "     |  +--+    ".chars().collect(),
-      "     A  |  C    ".chars().collect(),
-      " F---|----E|--+ ".chars().collect(),
-      "     |  |  |  D ".chars().collect(),
-      "     +B-+  +--+ ".chars().collect()
-    ];
-
-    assert_eq!(false, check_bounds(&map, 0,0, &Up));
-    assert_eq!(true, check_bounds(&map, 0,0, &Right));
-    assert_eq!(true, check_bounds(&map, 0,0, &Down));
-    assert_eq!(false, check_bounds(&map, 0,8, &Up));
-    assert_eq!(false, check_bounds(&map, 0,15, &Up));
-
-    assert_eq!(false, check_bounds(&map, 0,0, &Left));
-    assert_eq!(false, check_bounds(&map, 3,0, &Left));
-    assert_eq!(false, check_bounds(&map, 5,0, &Left));
-
-    assert_eq!(false, check_bounds(&map, 5,0, &Down));
-    assert_eq!(false, check_bounds(&map, 5,8, &Down));
-    assert_eq!(false, check_bounds(&map, 5,15, &Down));
-
-    assert_eq!(false, check_bounds(&map, 0,15, &Right));
-    assert_eq!(false, check_bounds(&map, 3,15, &Right));
-    assert_eq!(false, check_bounds(&map, 5,15, &Right));
+    let map = map(&EXAMPLE.lines().collect::<Vec<_>>());
+
+    assert!(!check_bounds(&map, 0,0, &Up));
+    assert!(check_bounds(&map, 0,0, &Right));
+    assert!(check_bounds(&map, 0,0, &Down));
+    assert!(!check_bounds(&map, 0,8, &Up));
+    assert!(!check_bounds(&map, 0,15, &Up));
+
+    assert!(!check_bounds(&map, 0,0, &Left));
+    assert!(!check_bounds(&map, 3,0, &Left));
+    assert!(!check_bounds(&map, 5,0, &Left));
+
+    assert!(!check_bounds(&map, 5,0, &Down));
+    assert!(!check_bounds(&map, 5,8, &Down));
+    assert!(!check_bounds(&map, 5,15, &Down));
+
+    assert!(!check_bounds(&map, 0,15, &Right));
+    assert!(!check_bounds(&map, 3,15, &Right));
+    assert!(!check_bounds(&map, 5,15, &Right));
   }
 
   #[test]
-  fn navigate_works() {
-    let input = vec![
-      "     |          ".chars().collect(),
-      "     |  +--+    ".chars().collect(),
-      "     A  |  C    ".chars().collect(),
-      " F---|----E|--+ ".chars().collect(),
-      "     |  |  |  D ".chars().collect(),
-      "     +B-+  +--+ ".chars().collect()
-    ];
-    assert_eq!(("ABCDEF".to_string(), 38), navigate(&input));
+  fn route_works() {
+    let network = Network::parse(EXAMPLE).unwrap();
+    let route = network.route().unwrap();
+
+    assert_eq!(vec![(at(0, 5), Down)], network.entries());
+    assert_eq!(("ABCDEF", 38), (route.letters.as_str(), route.steps));
+    assert_eq!(at(3, 1), route.end);
+    assert_eq!(8, network.segments().count());
   }
 
   #[test]
-  fn rotate_works() {
-    let m1 = vec![
-      "         |      ".chars().collect(),
-      "       --+      ".chars().collect(),
-      "                ".chars().collect(),
-    ];
+  fn exits_works() {
+    let m1 = map(&[
+      "         |      ",
+      "       --+      ",
+      "                ",
+    ]);
 
     // came to the junction from the left
-    assert_eq!(Up, rotate(&m1, Right, 1, 9));
+    assert_eq!(vec![Up], exits(&m1, Right, at(1, 9)));
 
     // came to the junction from the top
-    assert_eq!(Left, rotate(&m1, Down, 1, 9));
+    assert_eq!(vec![Left], exits(&m1, Down, at(1, 9)));
 
-    let m2 = vec![
-      "                ".chars().collect(),
-      "       +--      ".chars().collect(),
-      "       |        ".chars().collect(),
-    ];
+    let m2 = map(&[
+      "                ",
+      "       +--      ",
+      "       |        ",
+    ]);
 
     // came to the junction from the bottom
-    assert_eq!(Right, rotate(&m2, Up, 1, 7));
+    assert_eq!(vec![Right], exits(&m2, Up, at(1, 7)));
 
     // came to the junction from the right
-    assert_eq!(Down, rotate(&m2, Left, 1, 7));
+    assert_eq!(vec![Down], exits(&m2, Left, at(1, 7)));
+  }
+
+  #[test]
+  fn entries_can_be_on_any_edge() {
+    let network = Network::parse("   \n-A+\n  |\n  B\n  |").unwrap();
+
+    assert_eq!(vec![(at(1, 0), Right), (at(4, 2), Up)], network.entries());
+    let routes: Vec<_> = network.routes().into_iter().map(|r| r.unwrap()).collect();
+    assert_eq!(("AB", at(4, 2), 6), (routes[0].letters.as_str(), routes[0].end, routes[0].steps));
+    assert_eq!(("BA", at(1, 0), 6), (routes[1].letters.as_str(), routes[1].end, routes[1].steps));
+  }
+
+  #[test]
+  fn ambiguous_junctions_branch() {
+    let network = Network::parse("  |  \nB-+-C\n  A  ").unwrap();
+
+    assert_eq!(Err(RouteError::Ambiguous { row: 1, col: 2, exits: vec![Left, Right] }), network.route());
+    let letters: Vec<String> = network.paths().into_iter().filter(|p| p.entry == at(0, 2)).map(|p| p.letters).collect();
+    assert_eq!(vec!["B".to_string(), "C".to_string()], letters);
+  }
+
+  #[test]
+  fn bad_diagrams_are_errors() {
+    assert_eq!(Err(RouteError::DeadEnd { row: 1, col: 1 }), Network::parse(" | \n + \n   ").unwrap().route());
+    assert_eq!(Some(RouteError::InvalidTile { row: 1, col: 1, tile: '*' }), Network::parse(" | \n * ").err());
+    assert_eq!(Some(RouteError::NoEntry), Network::parse("   \n - \n   ").err());
+    assert_eq!("Junction at (1,1) has no way out", RouteError::DeadEnd { row: 1, col: 1 }.to_string());
   }
 }
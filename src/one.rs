@@ -1,29 +1,12 @@
-use common::read_file_contents;
+use common::{read_file_contents, Ring};
 
 fn base_captcha(input: &str, lookahead: usize) -> u32 {
-  let chars: Vec<char> = input.chars().collect();
-  let len = input.len();
+  let digits = Ring::new(input.chars().collect::<Vec<char>>());
 
-  if input.len() == 0 {
-    0
-  }
-  else if input.len() == 1 {
-    chars[0].to_digit(10).unwrap_or(0)
-  }
-  else {
-    let mut sum = 0_u32;
-    let correlated = lookahead..len+lookahead;
-    let indices = 0..len;
-
-    for (i, j) in indices.zip(correlated) {
-      let (a, b) = (chars[i], chars[j % len]);
-      if a.is_numeric() && a == b {
-        sum += a.to_digit(10).unwrap_or(0);
-      }
-    }
-
-    sum
-  }
+  digits.pairs(lookahead)
+  .filter(|&(a, b)| a == b)
+  .filter_map(|(a, _)| a.to_digit(10))
+  .sum()
 }
 
 fn captcha_sum(input: &str) -> u32 {
@@ -31,22 +14,20 @@ fn captcha_sum(input: &str) -> u32 {
 }
 
 fn captcha_sum_rot(input: &str) -> u32 {
-  let len = input.len();
-  let half = len / 2 as usize;
-  base_captcha(input, half)
+  base_captcha(input, input.len() / 2)
 }
 
 pub fn main(args: Vec<String>) {
   let input = read_file_contents(&args[1]);
   let seq = input.trim();
-  let sum = captcha_sum(&seq);
-  print!("Captcha is {:?}", sum);
+  let sum = captcha_sum(seq);
+  println!("Captcha is {:?}", sum);
 }
 
 pub fn main_plus(args: Vec<String>) {
   let input = read_file_contents(&args[1]);
   let seq = input.trim();
-  let sum = captcha_sum_rot(&seq);
+  let sum = captcha_sum_rot(seq);
   println!("Captcha is {:?}", sum);
 }
 
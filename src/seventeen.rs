@@ -1,38 +1,189 @@
+use common::Cursor;
 
-fn value_after_n_inserts(buffer: &mut Vec<usize>, step: usize, inserts: usize, value_after: usize) -> usize {
-  let mut ptr = 0;
-  for value in 1..inserts + 1 {
-    ptr = (ptr + step) % buffer.len();
-    buffer.insert(ptr + 1, value);
-    ptr += 1;
+/// A spinlock stepping `step` places forward before each insert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spinlock {
+  step: usize,
+}
+
+/// The circular buffer a spinlock leaves behind, indexed both ways.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Buffer {
+  values: Vec<u32>,
+  index_of: Vec<u32>,
+}
+
+/// The free slots of a buffer, as a Fenwick tree of counts that can find
+/// the k-th free slot in O(log n).
+struct FreeSlots {
+  tree: Vec<u32>,
+  top: usize,
+}
+
+impl FreeSlots {
+  fn new(slots: usize) -> FreeSlots {
+    let mut tree = vec![0; slots + 1];
+    for i in 1..slots + 1 {
+      tree[i] += 1;
+      let parent = i + (i & i.wrapping_neg());
+      if parent <= slots {
+        tree[parent] += tree[i];
+      }
+    }
+
+    let mut top = 1;
+    while top * 2 <= slots {
+      top *= 2;
+    }
+    FreeSlots { tree, top }
   }
 
-  let pos = buffer.iter().position(|&v|v==value_after).unwrap();
-  buffer[pos + 1]
+  /// Takes the `k`-th free slot, counting from 0, and returns its index.
+  fn take(&mut self, mut k: u32) -> usize {
+    let mut pos = 0;
+    let mut bit = self.top;
+    while bit > 0 {
+      let next = pos + bit;
+      if next < self.tree.len() && self.tree[next] <= k {
+        k -= self.tree[next];
+        pos = next;
+      }
+      bit >>= 1;
+    }
+
+    let mut i = pos + 1;
+    while i < self.tree.len() {
+      self.tree[i] -= 1;
+      i += i & i.wrapping_neg();
+    }
+    pos
+  }
 }
 
-fn spinlock(step: usize) -> usize {
-  let mut current = 0;
-  let mut one_slot = 0;
-  for v in 1..50_000_000+1 {
-    current = ((current + step) % v) + 1;
-    if current == 1 {
-      one_slot = v;
+impl Spinlock {
+  pub fn new(step: usize) -> Spinlock {
+    Spinlock { step }
+  }
+
+  /// The index each value from 1 to `inserts` is inserted at, in a buffer
+  /// holding the values before it.
+  fn insert_positions(&self, inserts: usize) -> Vec<u32> {
+    let mut positions = Vec::with_capacity(inserts + 1);
+    positions.push(0);
+    let mut cursor = Cursor::new(0, 1);
+    for _ in 0..inserts {
+      cursor.advance(self.step);
+      positions.push(cursor.insert_after() as u32);
     }
+    positions
+  }
+
+  /// The buffer after `inserts` inserts. Only later values move a value once
+  /// it is in, so going from the last value back, each one takes the free
+  /// slot its insert position counts to.
+  pub fn run(&self, inserts: usize) -> Buffer {
+    if inserts >= u32::MAX as usize {
+      panic!("Too many inserts for one buffer: {}", inserts);
+    }
+    let positions = self.insert_positions(inserts);
+    let mut free = FreeSlots::new(inserts + 1);
+    let mut values = vec![0; inserts + 1];
+    let mut index_of = vec![0; inserts + 1];
+
+    for value in (0..inserts + 1).rev() {
+      let index = free.take(positions[value]);
+      values[index] = value as u32;
+      index_of[value] = index as u32;
+    }
+
+    Buffer { values, index_of }
+  }
+
+  /// The value after 0 once `inserts` inserts are done. 0 never moves from
+  /// index 0, so only inserts at index 1 matter, and runs of inserts that
+  /// don't wrap around the buffer are skipped over in one go.
+  pub fn value_after_zero(&self, inserts: usize) -> usize {
+    let mut current = 0;
+    let mut after_zero = 0;
+    let mut len = 1;
+
+    while len <= inserts {
+      if let Some(skip) = (len - current - 1).checked_div(self.step) {
+        let skip = skip.min(inserts - len);
+        current += skip * (self.step + 1);
+        len += skip;
+      }
+
+      current = (current + self.step) % len + 1;
+      if current == 1 {
+        after_zero = len;
+      }
+      len += 1;
+    }
+
+    after_zero
   }
-  one_slot
+}
+
+impl Buffer {
+  pub fn len(&self) -> usize {
+    self.values.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.values.is_empty()
+  }
+
+  pub fn values(&self) -> &[u32] {
+    &self.values
+  }
+
+  pub fn value_at(&self, index: usize) -> usize {
+    self.values[index % self.len()] as usize
+  }
+
+  pub fn index_of(&self, value: usize) -> Option<usize> {
+    self.index_of.get(value).map(|&index| index as usize)
+  }
+
+  /// The value just after `value`, wrapping around the end of the buffer.
+  pub fn value_after(&self, value: usize) -> Option<usize> {
+    self.index_of(value).map(|index| self.value_at(index + 1))
+  }
+}
+
+fn parse_count(line: &str, what: &str) -> usize {
+  match line.trim().parse() {
+    Ok(count) => count,
+    Err(_) => panic!("Invalid {}: {}", what, line.trim()),
+  }
+}
+
+/// Parses the spinlock step, then the inserts for each part, one per line.
+fn parse_input(input: &str) -> (usize, usize, usize) {
+  let lines: Vec<&str> = input.lines().filter(|line| !line.trim().is_empty()).collect();
+  match lines.as_slice() {
+    [step, inserts, inserts_after_zero] => (
+      parse_count(step, "spinlock step"),
+      parse_count(inserts, "insert count"),
+      parse_count(inserts_after_zero, "insert count"),
+    ),
+    _ => panic!("Expected the spinlock step and two insert counts, one per line, not: {:?}", input),
+  }
+}
+
+fn value_after_n_inserts(step: usize, inserts: usize, value_after: usize) -> usize {
+  Spinlock::new(step).run(inserts).value_after(value_after).unwrap()
 }
 
 pub fn main () {
+  let (step, inserts, inserts_after_zero) = parse_input(include_str!("../input/seventeen"));
   {
-    let step = 377;
-    let inserts = 2017;
-    let mut buf = vec![0];
-    let answer = value_after_n_inserts(&mut buf, step, inserts, inserts);
-    println!("Position after 2017: {}", answer);
+    let answer = value_after_n_inserts(step, inserts, inserts);
+    println!("Position after {}: {}", inserts, answer);
   }
   {
-    let answer = spinlock(377);
+    let answer = Spinlock::new(step).value_after_zero(inserts_after_zero);
     println!("Position after 0: {}", answer);
   }
 }
@@ -41,10 +192,67 @@ pub fn main () {
 mod tests {
   use super::*;
 
+  fn insert_naively(step: usize, inserts: usize) -> Vec<u32> {
+    let mut buffer = vec![0];
+    let mut ptr = 0;
+    for value in 1..inserts + 1 {
+      ptr = (ptr + step) % buffer.len() + 1;
+      buffer.insert(ptr, value as u32);
+    }
+    buffer
+  }
+
+  #[test]
+  fn parse_input_reads_the_step_and_insert_counts() {
+    assert_eq!((3, 2017, 50_000_000), parse_input("3\n2017\n50000000\n"));
+    assert_eq!((377, 9, 10), parse_input(" 377 \n\n9\n10"));
+  }
+
+  #[test]
+  #[should_panic(expected = "Expected the spinlock step and two insert counts")]
+  fn parse_input_needs_the_insert_counts() {
+    parse_input("377\n");
+  }
+
   #[test]
   fn value_after_n_inserts_works() {
-    assert_eq!(1, value_after_n_inserts(&mut vec![0], 3, 3, 3));
-    assert_eq!(638, value_after_n_inserts(&mut vec![0], 3, 2017, 2017));
-    assert_eq!(151, value_after_n_inserts(&mut vec![0], 3, 2017, 1134));
+    assert_eq!(1, value_after_n_inserts(3, 3, 3));
+    assert_eq!(638, value_after_n_inserts(3, 2017, 2017));
+    assert_eq!(151, value_after_n_inserts(3, 2017, 1134));
+  }
+
+  #[test]
+  fn run_matches_inserting_naively() {
+    for step in 0..12 {
+      for inserts in 0..40 {
+        let buffer = Spinlock::new(step).run(inserts);
+        assert_eq!(insert_naively(step, inserts), buffer.values());
+        for (index, &value) in buffer.values().iter().enumerate() {
+          assert_eq!(Some(index), buffer.index_of(value as usize));
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn buffer_answers_queries() {
+    let buffer = Spinlock::new(3).run(9);
+
+    assert_eq!(&[0, 9, 5, 7, 2, 4, 3, 8, 6, 1], buffer.values());
+    assert_eq!(9, buffer.value_at(1));
+    assert_eq!(0, buffer.value_at(10));
+    assert_eq!(Some(0), buffer.value_after(1));
+    assert_eq!(Some(8), buffer.value_after(3));
+    assert_eq!(None, buffer.value_after(10));
+  }
+
+  #[test]
+  fn value_after_zero_matches_the_buffer() {
+    for step in 0..20 {
+      for &inserts in &[0, 1, 2, 17, 500, 2017] {
+        let buffer = Spinlock::new(step).run(inserts);
+        assert_eq!(buffer.value_after(0).unwrap(), Spinlock::new(step).value_after_zero(inserts));
+      }
+    }
   }
 }
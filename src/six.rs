@@ -1,9 +1,9 @@
 
 use std::cmp::Ordering;
 use std::collections::HashSet;
-use common::read_file_contents;
+use common::{read_file_contents, Ring};
 
-fn choose_bank<'a>(banks: &'a Vec<u32>) -> (usize, &u32) {
+fn choose_bank(banks: &[u32]) -> (usize, &u32) {
   banks.iter().enumerate().max_by(|&(i_1, n_1), &(i_2, n_2)| {
     let ord = n_1.cmp(n_2);
     match ord {
@@ -14,28 +14,25 @@ fn choose_bank<'a>(banks: &'a Vec<u32>) -> (usize, &u32) {
   .unwrap()
 }
 
-fn redistribute_bank(banks: &mut Vec<u32>, bank: usize) {
-  // take blocks from the bank that was chosen
-  let mut blocks = banks[bank];
-  let mut ptr = bank;
+fn redistribute_bank(banks: &mut [u32], bank: usize) {
+  let mut banks = Ring::new(banks);
 
-  banks[ptr] = 0;
+  // take blocks from the bank that was chosen
+  let blocks = banks[bank];
+  banks[bank] = 0;
 
   // start at the bank after the one we started at
-  ptr = (ptr + 1) % banks.len();
-
-  while blocks > 0 {
-    blocks -= 1;
-    banks[ptr] += 1;
-    ptr = (ptr + 1) % banks.len();
+  let mut ptr = banks.cursor(bank);
+  for _ in 0..blocks {
+    banks[ptr.advance(1)] += 1;
   }
 }
 
-fn serialize_banks(banks: &Vec<u32>) -> String {
+fn serialize_banks(banks: &[u32]) -> String {
   format!("{:?}", banks)
 }
 
-fn count_steps_for_loop(banks: &mut Vec<u32>) -> usize {
+fn count_steps_for_loop(banks: &mut [u32]) -> usize {
   let mut mem = HashSet::new();
   let mut steps = 0;
 
@@ -76,12 +73,12 @@ mod tests {
 
   #[test]
   fn choose_bank_chooses_the_largest_bank() {
-    assert_eq!((2, &7), choose_bank(&vec![0, 2, 7, 0]));
-    assert_eq!((2, &8), choose_bank(&vec![0, 2, 8, 0]));
-    assert_eq!((2, &8), choose_bank(&vec![0, 5, 8, 0]));
+    assert_eq!((2, &7), choose_bank(&[0, 2, 7, 0]));
+    assert_eq!((2, &8), choose_bank(&[0, 2, 8, 0]));
+    assert_eq!((2, &8), choose_bank(&[0, 5, 8, 0]));
 
     // handles equal banks by choosing the first one
-    assert_eq!((0, &22), choose_bank(&vec![22, 22, 22, 22]));
+    assert_eq!((0, &22), choose_bank(&[22, 22, 22, 22]));
   }
 
   #[test]
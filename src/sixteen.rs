@@ -1,60 +1,286 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// A line of programs, each with a name and an id from 0 in the order they
+/// started in. The line is a ring of slots with a movable front, and every
+/// program's slot is indexed, so each dance step takes O(1) time however
+/// long the line is.
+#[derive(Debug, Clone)]
+pub struct Programs {
+  names: Rc<Vec<String>>,
+  ids: Rc<HashMap<String, usize>>,
+  /// `slots[s]` is the id of the program in slot `s`.
+  slots: Vec<usize>,
+  /// `slot_of[id]` is the slot holding program `id`.
+  slot_of: Vec<usize>,
+  /// The slot at the front of the line.
+  front: usize,
+}
+
+impl PartialEq for Programs {
+  fn eq(&self, other: &Programs) -> bool {
+    self.names == other.names && self.order() == other.order()
+  }
+}
+
+impl Programs {
+  /// A line of programs with the given names, in order.
+  pub fn from_names<S: Into<String>>(names: Vec<S>) -> Programs {
+    let names: Vec<String> = names.into_iter().map(Into::into).collect();
+    let mut ids = HashMap::new();
+    for (id, name) in names.iter().enumerate() {
+      if ids.insert(name.clone(), id).is_some() {
+        panic!("More than one program is named {}", name);
+      }
+    }
+
+    let n = names.len();
+    Programs {
+      names: Rc::new(names),
+      ids: Rc::new(ids),
+      slots: (0..n).collect(),
+      slot_of: (0..n).collect(),
+      front: 0,
+    }
+  }
+
+  /// `programs` programs named by their ids, `0` upwards.
+  pub fn numbered(programs: usize) -> Programs {
+    Programs::from_names((0..programs).map(|id| id.to_string()).collect())
+  }
+
+  pub fn len(&self) -> usize {
+    self.slots.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.slots.is_empty()
+  }
 
-type Programs = Vec<char>;
+  fn slot(&self, position: usize) -> usize {
+    (self.front + position) % self.len()
+  }
+
+  fn id(&self, name: &str) -> usize {
+    match self.ids.get(name) {
+      Some(&id) => id,
+      None => panic!("No program is named {}", name),
+    }
+  }
+
+  /// The ids of the programs, from the front of the line.
+  pub fn order(&self) -> Vec<usize> {
+    (0..self.len()).map(|position| self.slots[self.slot(position)]).collect()
+  }
+
+  /// The names of the programs, from the front of the line.
+  pub fn names(&self) -> Vec<&str> {
+    self.order().into_iter().map(|id| self.names[id].as_str()).collect()
+  }
 
-#[derive(Debug, PartialEq)]
-enum Step {
+  /// The same programs, with program `order[i]` at position `i`.
+  fn with_order(&self, order: Vec<usize>) -> Programs {
+    let mut slot_of = vec![0; order.len()];
+    for (slot, &id) in order.iter().enumerate() {
+      slot_of[id] = slot;
+    }
+    Programs { names: self.names.clone(), ids: self.ids.clone(), slots: order, slot_of, front: 0 }
+  }
+
+  fn swap_slots(&mut self, left: usize, right: usize) {
+    self.slots.swap(left, right);
+    self.slot_of[self.slots[left]] = left;
+    self.slot_of[self.slots[right]] = right;
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
   Spin(usize),
   Exchange(usize,usize),
-  Partner(char, char)
+  Partner(String, String)
 }
 
-fn create_programs(programs: usize) -> Programs {
-  let mut p = vec![];
-  for c in 0..programs as u8 {
-    let ch = 'a' as u8 + c;
-    p.push(ch as char);
+impl fmt::Display for Step {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Step::Spin(amount) => write!(f, "s{}", amount),
+      Step::Exchange(left, right) => write!(f, "x{}/{}", left, right),
+      Step::Partner(ref a, ref b) => write!(f, "p{}/{}", a, b),
+    }
   }
-  p
 }
 
-fn format_programs(programs: &Programs) -> String {
-  let mut fmt = String::new();
+/// Writes steps back out in the puzzle's `s1,x3/4,pe/b` syntax.
+pub fn format_steps(steps: &[Step]) -> String {
+  steps.iter().map(|step| step.to_string()).collect::<Vec<_>>().join(",")
+}
+
+/// The positions to swap to turn the identity order into `target`, placing
+/// each position in turn by swapping in the element that belongs there. Each swap completes at
+/// least one element, so a permutation of n elements with c cycles takes
+/// n - c swaps, which is the fewest possible.
+fn transpositions(target: &[usize]) -> Vec<(usize, usize)> {
+  let mut current: Vec<usize> = (0..target.len()).collect();
+  let mut position_of = current.clone();
+  let mut swaps = Vec::new();
+
+  for (i, &wanted) in target.iter().enumerate() {
+    if current[i] != wanted {
+      let j = position_of[wanted];
+      swaps.push((i, j));
+      current.swap(i, j);
+      position_of[current[i]] = i;
+      position_of[current[j]] = j;
+    }
+  }
+
+  swaps
+}
+
+/// Rewrites a dance for `programs` as the shortest equivalent one: exchanges,
+/// then at most one spin, then partner swaps. A spin followed by an exchange
+/// is the same as the exchange shifted back by the spin and then the spin, so
+/// any positional moves are some exchanges and one spin; every spin is tried,
+/// and the one leaving the fewest exchanges is kept. Partner swaps commute
+/// with positional moves, and need one swap fewer than each cycle's length.
+pub fn optimize(steps: &[Step], programs: &Programs) -> Vec<Step> {
+  if programs.is_empty() {
+    panic!("Can't optimize a dance for a line of no programs");
+  }
+  let n = programs.len();
+  let dance = Dance::compile(steps, programs);
+
+  // exchanges then a spin of `spin` start from the order `positions` has
+  // before the spin, so position `i` there holds what ends up at `i + spin`
+  let (spin, exchanges) = (0..n)
+    .map(|spin| (spin, transpositions(&(0..n).map(|i| dance.positions[(i + spin) % n]).collect::<Vec<_>>())))
+    .min_by_key(|&(spin, ref swaps)| swaps.len() + if spin == 0 { 0 } else { 1 })
+    .unwrap();
+
+  let mut optimized: Vec<Step> = exchanges.into_iter().map(|(i, j)| Step::Exchange(i, j)).collect();
+  if spin != 0 {
+    optimized.push(Step::Spin(spin));
+  }
+
+  // partner swaps name the programs in the swapped places
+  let mut line: Vec<usize> = (0..n).collect();
+  optimized.extend(transpositions(&dance.labels).into_iter().map(|(i, j)| {
+    let step = Step::Partner(programs.names[line[i]].clone(), programs.names[line[j]].clone());
+    line.swap(i, j);
+    step
+  }));
+  optimized
+}
 
-  for ch in programs {
-    fmt.push(*ch);
+/// Programs named `a` onwards, one letter each, as in the puzzle.
+fn create_programs(programs: usize) -> Programs {
+  if programs > 26 {
+    panic!("There are only letters for 26 programs, not {}", programs);
   }
+  Programs::from_names((0..programs as u8).map(|c| ((b'a' + c) as char).to_string()).collect())
+}
 
-  fmt
+fn format_programs(programs: &Programs) -> String {
+  programs.names().concat()
 }
 
-fn perform_step(programs: &mut Programs, step: Step) {
-  match step {
+fn perform_step(programs: &mut Programs, step: &Step) {
+  match *step {
     Step::Spin(amount) => {
-      for _ in 0..amount {
-        let pr = programs.pop().unwrap();
-        programs.insert(0, pr);
-      }
+      let len = programs.len();
+      programs.front = (programs.front + len - amount % len) % len;
     },
     Step::Exchange(left, right) => {
-      programs.swap(left, right);
+      let (left, right) = (programs.slot(left), programs.slot(right));
+      programs.swap_slots(left, right);
     },
-    Step::Partner(a, b) => {
-      let left = programs.iter().position(|&p|p==a).unwrap();
-      let right = programs.iter().position(|&p|p==b).unwrap();
-      programs.swap(left, right);
+    Step::Partner(ref a, ref b) => {
+      let left = programs.slot_of[programs.id(a)];
+      let right = programs.slot_of[programs.id(b)];
+      programs.swap_slots(left, right);
     }
   }
 }
 
-fn do_the_dance<I>(programs: &mut Programs, steps: &mut I) where I:Iterator<Item=Step> {
+fn do_the_dance<'a, I>(programs: &mut Programs, steps: &mut I) where I:Iterator<Item=&'a Step> {
   for step in steps {
     perform_step(programs, step);
   }
 }
 
+/// A dance compiled into two permutations. Spins and exchanges move programs
+/// by position whatever their names, and partner swaps move them by name
+/// wherever they stand, so the two kinds of move commute: a dance is the
+/// positional moves and the renames applied separately.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dance {
+  /// `positions[i]` is the position the program ending up at `i` starts from.
+  positions: Vec<usize>,
+  /// `labels[x]` is the id that program `x`'s place in the line passes to.
+  labels: Vec<usize>,
+}
+
+impl Dance {
+  /// A dance that leaves every program where it is.
+  pub fn identity(programs: usize) -> Dance {
+    Dance { positions: (0..programs).collect(), labels: (0..programs).collect() }
+  }
+
+  /// Compiles `steps` for a line of `programs`, by dancing the positional
+  /// steps and the partner steps separately on lines in their starting order.
+  pub fn compile(steps: &[Step], programs: &Programs) -> Dance {
+    let start = programs.with_order((0..programs.len()).collect());
+    let (mut moves, mut renames) = (start.clone(), start);
+
+    for step in steps {
+      match *step {
+        Step::Partner(..) => perform_step(&mut renames, step),
+        _ => perform_step(&mut moves, step),
+      }
+    }
+
+    Dance { positions: moves.order(), labels: renames.order() }
+  }
+
+  /// This dance followed by `next`.
+  pub fn then(&self, next: &Dance) -> Dance {
+    Dance {
+      positions: next.positions.iter().map(|&i| self.positions[i]).collect(),
+      labels: self.labels.iter().map(|&x| next.labels[x]).collect(),
+    }
+  }
+
+  /// This dance performed `n` times in a row, by repeated squaring.
+  pub fn pow(&self, mut n: usize) -> Dance {
+    let mut result = Dance::identity(self.positions.len());
+    let mut square = self.clone();
+
+    while n > 0 {
+      if n & 1 == 1 {
+        result = result.then(&square);
+      }
+      square = square.then(&square);
+      n >>= 1;
+    }
+
+    result
+  }
+
+  pub fn apply(&self, programs: &Programs) -> Programs {
+    let order = programs.order();
+    programs.with_order(self.positions.iter().map(|&i| self.labels[order[i]]).collect())
+  }
+}
+
+/// The line after dancing `steps` `n` times over.
+pub fn dance_n_times(steps: &[Step], programs: &Programs, n: usize) -> Programs {
+  Dance::compile(steps, programs).pow(n).apply(programs)
+}
+
 fn decode_step(step_str: &str) -> Step {
-  match step_str.chars().nth(0).unwrap() {
+  match step_str.chars().next().unwrap() {
     's' => {
       let amt: usize = step_str[1..].parse().unwrap();
       Step::Spin(amt)
@@ -67,8 +293,8 @@ fn decode_step(step_str: &str) -> Step {
     },
     'p' => {
       let mut names = step_str[1..].split('/');
-      let x: char = names.next().unwrap().chars().nth(0).unwrap();
-      let y: char = names.next().unwrap().chars().nth(0).unwrap();
+      let x = names.next().unwrap().to_string();
+      let y = names.next().unwrap().to_string();
       Step::Partner(x, y)
     },
     step => panic!("Invalid step encoding: {}", step)
@@ -77,44 +303,21 @@ fn decode_step(step_str: &str) -> Step {
 
 fn main_1<'a,I>(input: &mut I) where I:Iterator<Item=&'a str> {
   let mut p = create_programs(16);
-  do_the_dance(&mut p, &mut input.map(decode_step));
+  let steps: Vec<Step> = input.map(decode_step).collect();
+  do_the_dance(&mut p, &mut steps.iter());
   let program_output = format_programs(&p);
   println!("Program output = {}", program_output);
 }
 
 fn main_2() {
-  let mut p = create_programs(16);
-  let mut dances = 0;
-  let mut configurations = HashMap::new();
-
-  configurations.insert(format_programs(&p), 0);
-
-  let input = &mut include_str!("../input/sixteen").split(',').cycle().map(decode_step);
-
-  loop {
-    do_the_dance(&mut p, &mut input.take(10_000));
-    let cfg = format_programs(&p);
-    dances += 1;
-
-    if configurations.contains_key(&cfg) {
-      let first_seen = *configurations.get(&cfg).unwrap();
-      let offset = (1_000_000_000 % (dances - first_seen)) - first_seen;
-      for (hash, o) in configurations.clone() {
-        if offset == o {
-          println!("Configuration after a billion: {}", hash);
-          return;
-        }
-      }
-    }
-    else {
-      configurations.insert(cfg, dances);
-    }
-  }
+  let steps: Vec<Step> = include_str!("../input/sixteen").trim().split(',').map(decode_step).collect();
+  let p = dance_n_times(&steps, &create_programs(16), 1_000_000_000);
+  println!("Configuration after a billion: {}", format_programs(&p));
 }
 
 pub fn main() {
-  // let mut input1 = include_str!("../input/sixteen").split(',');
-  // main_1(&mut input1);
+  let mut input1 = include_str!("../input/sixteen").trim().split(',');
+  main_1(&mut input1);
 
   main_2();
 }
@@ -123,36 +326,48 @@ pub fn main() {
 mod tests {
   use super::*;
 
+  fn line(letters: &str) -> Programs {
+    Programs::from_names(letters.chars().map(|ch| ch.to_string()).collect())
+  }
+
+  fn partner(a: &str, b: &str) -> Step {
+    Step::Partner(a.to_string(), b.to_string())
+  }
+
   #[test]
   fn perform_spin_works() {
-    let mut p = vec!['a','b','c','d','e'];
+    let mut p = line("abcde");
+
+    perform_step(&mut p, &Step::Spin(1));
+
+    assert_eq!("eabcd", format_programs(&p));
 
-    perform_step(&mut p, Step::Spin(1));
+    perform_step(&mut p, &Step::Spin(13));
 
-    assert_eq!(vec!['e','a','b','c','d'], p);
+    assert_eq!("bcdea", format_programs(&p));
   }
 
   #[test]
   fn perform_exchange_works() {
-    let mut p = vec!['e','a','b','c','d'];
+    let mut p = line("eabcd");
 
-    perform_step(&mut p, Step::Exchange(3,4));
+    perform_step(&mut p, &Step::Exchange(3,4));
 
-    assert_eq!(vec!['e','a','b','d','c'], p);
+    assert_eq!("eabdc", format_programs(&p));
   }
 
   #[test]
   fn perform_partner_works() {
-    let mut p = vec!['e','a','b','d','c'];
+    let mut p = line("eabdc");
 
-    perform_step(&mut p, Step::Partner('e', 'b'));
+    perform_step(&mut p, &partner("e", "b"));
 
-    assert_eq!(vec!['b','a','e','d','c'], p);
+    assert_eq!("baedc", format_programs(&p));
   }
 
   #[test]
   fn format_programs_works() {
-    let p = vec!['a','z','q','x','b','n'];
+    let p = line("azqxbn");
     assert_eq!("azqxbn".to_string(), format_programs(&p));
   }
 
@@ -160,22 +375,164 @@ mod tests {
   fn decode_step_works() {
     assert_eq!(Step::Spin(7), decode_step("s7"));
     assert_eq!(Step::Exchange(7, 12), decode_step("x7/12"));
-    assert_eq!(Step::Partner('n', 'b'), decode_step("pn/b"));
+    assert_eq!(partner("n", "b"), decode_step("pn/b"));
+    assert_eq!(partner("1024", "alice"), decode_step("p1024/alice"));
   }
 
   #[test]
   fn create_programs_works() {
-    assert_eq!(vec!['a','b','c','d','e'], create_programs(5));
-    assert_eq!('p', *create_programs(16).iter().last().unwrap());
+    assert_eq!(line("abcde"), create_programs(5));
+    assert_eq!(Some(&"p"), create_programs(16).names().last());
   }
 
   #[test]
   fn do_the_dance_works() {
     let mut p = create_programs(5);
-    let mut steps = "s1,x3/4,pe/b".split(",").map(decode_step);
+    let steps: Vec<Step> = "s1,x3/4,pe/b".split(",").map(decode_step).collect();
 
-    do_the_dance(&mut p, &mut steps);
+    do_the_dance(&mut p, &mut steps.iter());
 
     assert_eq!("baedc".to_string(), format_programs(&p));
   }
+
+  #[test]
+  fn compiled_dance_matches_the_example() {
+    let steps: Vec<Step> = "s1,x3/4,pe/b".split(',').map(decode_step).collect();
+    let dance = Dance::compile(&steps, &create_programs(5));
+
+    assert_eq!("baedc", format_programs(&dance.apply(&create_programs(5))));
+    assert_eq!("ceadb", format_programs(&dance.pow(2).apply(&create_programs(5))));
+    assert_eq!(Dance::identity(5), dance.pow(0));
+  }
+
+  #[test]
+  fn dance_n_times_matches_dancing_repeatedly() {
+    let steps: Vec<Step> = include_str!("../input/sixteen").trim().split(',').map(decode_step).collect();
+    let start = create_programs(16);
+
+    let mut p = start.clone();
+    for n in 1..13 {
+      do_the_dance(&mut p, &mut steps.iter());
+      assert_eq!(p, dance_n_times(&steps, &start, n));
+    }
+  }
+
+  #[test]
+  fn optimize_normalizes_the_example() {
+    let steps: Vec<Step> = "s1,x3/4,pe/b,s3,x0/1,x1/0,pb/e,pa/a".split(',').map(decode_step).collect();
+    let optimized = optimize(&steps, &create_programs(5));
+
+    assert_eq!("x2/3,s4", format_steps(&optimized));
+
+    let mut p = create_programs(5);
+    do_the_dance(&mut p, &mut optimized.iter());
+    let mut q = create_programs(5);
+    do_the_dance(&mut q, &mut steps.iter());
+    assert_eq!(q, p);
+  }
+
+  #[test]
+  fn optimize_matches_dancing_on_random_inputs() {
+    let mut seed: u64 = 0x2017_1216;
+    let mut random = |bound: usize| {
+      seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+      (seed >> 33) as usize % bound
+    };
+
+    for _ in 0..200 {
+      let programs = 1 + random(16);
+      let steps: Vec<Step> = (0..random(60)).map(|_| match random(3) {
+        0 => Step::Spin(random(40)),
+        1 => Step::Exchange(random(programs), random(programs)),
+        _ => {
+          let name = |i: usize| ((b'a' + i as u8) as char).to_string();
+          Step::Partner(name(random(programs)), name(random(programs)))
+        }
+      }).collect();
+      let optimized = optimize(&steps, &create_programs(programs));
+      assert!(optimized.len() <= steps.len());
+
+      let mut expected = create_programs(programs);
+      do_the_dance(&mut expected, &mut steps.iter());
+      let mut actual = create_programs(programs);
+      let reparsed: Vec<Step> = format_steps(&optimized).split(',').filter(|s| !s.is_empty()).map(decode_step).collect();
+      do_the_dance(&mut actual, &mut reparsed.iter());
+      assert_eq!(expected, actual, "{}", format_steps(&steps));
+    }
+  }
+
+  fn cycles(permutation: &[usize]) -> usize {
+    let mut seen = vec![false; permutation.len()];
+    let mut cycles = 0;
+    for start in 0..permutation.len() {
+      if !seen[start] {
+        cycles += 1;
+        let mut i = start;
+        while !seen[i] {
+          seen[i] = true;
+          i = permutation[i];
+        }
+      }
+    }
+    cycles
+  }
+
+  #[test]
+  fn optimize_cancels_swaps_that_are_not_adjacent() {
+    let steps: Vec<Step> = "x0/1,x2/3,x0/1,pa/b,pc/d,pa/b".split(',').map(decode_step).collect();
+    assert_eq!("x2/3,pc/d", format_steps(&optimize(&steps, &create_programs(6))));
+  }
+
+  #[test]
+  fn optimize_takes_one_swap_fewer_than_each_cycle() {
+    let mut seed: u64 = 0x2017_1216;
+    let mut random = |bound: usize| {
+      seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+      (seed >> 33) as usize % bound
+    };
+
+    for n in 1..17 {
+      let programs = create_programs(n);
+      let name = |i: usize| ((b'a' + i as u8) as char).to_string();
+      let steps: Vec<Step> = (0..random(40)).map(|_| Step::Partner(name(random(n)), name(random(n)))).collect();
+      let dance = Dance::compile(&steps, &programs);
+
+      let optimized = optimize(&steps, &programs);
+      assert_eq!(n - cycles(&dance.labels), optimized.len());
+      assert_eq!(dance, Dance::compile(&optimized, &programs));
+    }
+
+    // exchanges are counted the same way, unless a spin saves some of them
+    let steps: Vec<Step> = "x0/5,x5/3,x1/2".split(',').map(decode_step).collect();
+    let dance = Dance::compile(&steps, &create_programs(6));
+    assert_eq!(3, cycles(&dance.positions));
+    assert_eq!(3, optimize(&steps, &create_programs(6)).len());
+
+    let steps: Vec<Step> = "x0/1,x1/2,x2/3,x3/4".split(',').map(decode_step).collect();
+    assert_eq!(vec![Step::Spin(4)], optimize(&steps, &create_programs(5)));
+  }
+
+  #[test]
+  #[should_panic(expected = "Can't optimize a dance for a line of no programs")]
+  fn optimize_rejects_empty_lines() {
+    optimize(&[Step::Spin(1)], &Programs::numbered(0));
+  }
+
+  #[test]
+  fn dances_with_named_and_numbered_programs() {
+    let mut p = Programs::from_names(vec!["alice", "bob", "carol"]);
+    let steps: Vec<Step> = "s1,pbob/carol,x0/2".split(',').map(decode_step).collect();
+    do_the_dance(&mut p, &mut steps.iter());
+    assert_eq!(vec!["carol", "alice", "bob"], p.names());
+
+    let numbered = Programs::numbered(5000);
+    let steps = vec![Step::Spin(4999), partner("0", "4999"), Step::Exchange(0, 2500)];
+    let mut p = numbered.clone();
+    do_the_dance(&mut p, &mut steps.iter());
+
+    let names = p.names();
+    assert_eq!(("2501", "2", "0", "4999"), (names[0], names[1], names[4998], names[4999]));
+    assert_eq!("1", names[2500]);
+    assert_eq!(p, dance_n_times(&steps, &numbered, 1));
+  }
 }
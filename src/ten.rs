@@ -1,67 +1,290 @@
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::hash::Hasher;
+use rayon::prelude::*;
+use common::Ring;
 
-fn compute_extended_lengths(input: String) -> Vec<usize> {
+const STANDARD_SUFFIX: [u8; 5] = [17, 31, 73, 47, 23];
+
+fn compute_extended_lengths(input: &[u8], suffix: &[u8]) -> Vec<usize> {
   let mut extended_lengths = Vec::new();
 
-  for ch in input.chars() {
-    extended_lengths.push(ch as usize);
+  for &byte in input.iter().chain(suffix) {
+    extended_lengths.push(byte as usize);
   }
 
-  extended_lengths.extend_from_slice(&[17, 31, 73, 47, 23]);
   extended_lengths
 }
 
-fn reverse_slice_segment(slice: &[u8], from: usize, length: usize) -> Vec<u8> {
-  let mut cycle: Vec<u8> = slice.iter().cycle().skip(from).take(length)
-  .map(|n| *n).collect();
-  cycle.reverse();
+fn run_rounds(ring: &mut [u8], lengths: &[usize], iterations: usize) {
+  let mut ring = Ring::new(ring);
+  if let Some(length) = lengths.iter().find(|&&length| length > ring.len()) {
+    panic!("Length {} is longer than the ring of {} elements", length, ring.len());
+  }
+
+  let mut pos = ring.cursor(0);
+  let mut skip = 0;
 
-  cycle
+  for _ in 0..iterations {
+    for &length in lengths {
+      ring.reverse(pos.position(), length);
+      pos.advance(length + skip);
+      skip += 1;
+    }
+  }
 }
 
-fn hash_slice(lengths: &[usize], iterations: usize) -> Vec<u8> {
-  let mut input_vec: Vec<u8> = (0..255u8).collect();
-  input_vec.push(255);
+/// A ring of `ring_size` elements, numbered from 0. A ring never has more
+/// than 256 elements, so it can always live in a fixed array on the stack.
+fn new_ring(ring_size: usize) -> [u8; 256] {
+  let mut ring = [0; 256];
+  for (i, n) in ring.iter_mut().enumerate().take(ring_size) {
+    *n = i as u8;
+  }
+  ring
+}
 
-  let input_len = input_vec.len();
+fn hash_slice(lengths: &[usize], ring_size: usize, iterations: usize) -> Vec<u8> {
+  let mut ring = new_ring(ring_size);
+  run_rounds(&mut ring[..ring_size], lengths, iterations);
+  ring[..ring_size].to_vec()
+}
 
-  let mut pos = 0;
-  let mut skip = 0;
+fn dense_hash(sparse_hash: &[u8], block_size: usize) -> Vec<u8> {
+  sparse_hash.chunks(block_size)
+  .map(|block| block.iter().fold(0, |h, i| h ^ i))
+  .collect()
+}
 
-  for _ in 0..iterations {
-    for length in lengths {
-      let reversed = reverse_slice_segment(&input_vec, pos, *length);
-      let mut i = pos;
-      for n in reversed {
-        input_vec[i] = n;
-        i = (i + 1) % input_len;
-      }
+/// The shape of a knot hash: how many elements are in the ring, how many
+/// rounds are run, the lengths appended to every input, and how many elements
+/// of the sparse hash are XORed into each byte of the dense hash. The default
+/// is the hash from the puzzle, with a 16-byte result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnotHashParams {
+  ring_size: usize,
+  rounds: usize,
+  suffix: Vec<u8>,
+  block_size: usize,
+}
 
-      pos = (pos + length + skip) % input_len;
-      skip += 1;
+impl Default for KnotHashParams {
+  fn default() -> Self {
+    KnotHashParams {
+      ring_size: 256,
+      rounds: 64,
+      suffix: STANDARD_SUFFIX.to_vec(),
+      block_size: 16,
+    }
+  }
+}
+
+impl KnotHashParams {
+  pub fn new() -> Self {
+    KnotHashParams::default()
+  }
+
+  /// Ring elements are bytes, so there can be at most 256.
+  pub fn ring_size(mut self, ring_size: usize) -> Self {
+    if ring_size == 0 || ring_size > 256 {
+      panic!("Ring size must be between 1 and 256, not {}", ring_size);
     }
+    self.ring_size = ring_size;
+    self
+  }
+
+  pub fn rounds(mut self, rounds: usize) -> Self {
+    self.rounds = rounds;
+    self
+  }
+
+  pub fn suffix(mut self, suffix: &[u8]) -> Self {
+    self.suffix = suffix.to_vec();
+    self
+  }
+
+  pub fn block_size(mut self, block_size: usize) -> Self {
+    if block_size == 0 {
+      panic!("Block size must be at least 1");
+    }
+    self.block_size = block_size;
+    self
+  }
+
+  /// The length in bytes of a hash: one byte per block of the ring.
+  pub fn hash_len(&self) -> usize {
+    (self.ring_size + self.block_size - 1) / self.block_size
+  }
+
+  /// The ring after every round of `lengths`, followed by the suffix.
+  pub fn sparse_hash(&self, lengths: &[usize]) -> Vec<u8> {
+    let suffix = self.suffix.iter().map(|&length| length as usize);
+    let lengths: Vec<usize> = lengths.iter().cloned().chain(suffix).collect();
+    hash_slice(&lengths, self.ring_size, self.rounds)
+  }
+
+  /// Hashes `input`, using each byte as a length. The result has one byte per
+  /// block of the ring, with a shorter last block if the block size doesn't
+  /// divide the ring size.
+  pub fn hash(&self, input: &[u8]) -> Vec<u8> {
+    let lengths = compute_extended_lengths(input, &self.suffix);
+    dense_hash(&hash_slice(&lengths, self.ring_size, self.rounds), self.block_size)
   }
+}
 
-  input_vec
+/// The knot hash with the standard parameters, without allocating a result.
+fn standard_hash(input: &[u8]) -> [u8; 16] {
+  let lengths = compute_extended_lengths(input, &STANDARD_SUFFIX);
+  let mut ring = new_ring(256);
+  run_rounds(&mut ring, &lengths, 64);
+
+  let mut hash = [0; 16];
+  hash.copy_from_slice(&dense_hash(&ring, 16));
+  hash
 }
 
-pub fn knot_hash(input: String) -> String {
-  let ext_lengths = compute_extended_lengths(input);
-  let sparse_hash = hash_slice(ext_lengths.as_slice(), 64);
+/// Hashes every input with the standard parameters, in parallel.
+pub fn knot_hash_batch<T: AsRef<[u8]> + Sync>(inputs: &[T]) -> Vec<[u8; 16]> {
+  inputs.par_iter().map(|input| standard_hash(input.as_ref())).collect()
+}
+
+/// Computes a knot hash from input given a piece at a time. Every round of
+/// the hash goes over the whole input, so it's buffered until `finalize`.
+#[derive(Debug, Clone, Default)]
+pub struct KnotHasher {
+  input: Vec<u8>,
+}
+
+impl KnotHasher {
+  pub fn new() -> Self {
+    KnotHasher::default()
+  }
+
+  pub fn update(&mut self, bytes: &[u8]) {
+    self.input.extend_from_slice(bytes);
+  }
+
+  fn digest(&self) -> [u8; 16] {
+    standard_hash(&self.input)
+  }
+
+  pub fn finalize(self) -> [u8; 16] {
+    self.digest()
+  }
+}
+
+impl Hasher for KnotHasher {
+  fn write(&mut self, bytes: &[u8]) {
+    self.update(bytes);
+  }
+
+  /// The first 8 bytes of the hash.
+  fn finish(&self) -> u64 {
+    self.digest()[..8].iter().fold(0, |h, &byte| h << 8 | byte as u64)
+  }
+}
+
+/// Writes a hash as lowercase hex, two digits per byte.
+pub fn to_hex(hash: &[u8]) -> String {
+  hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// A hash's bits, most significant bit of the first byte first.
+pub fn to_bits(hash: &[u8]) -> Vec<bool> {
+  hash.iter().flat_map(|&byte| (0..8).rev().map(move |bit| byte >> bit & 1 == 1)).collect()
+}
+
+/// The first `bits` bits of a hash, as a number.
+pub fn hash_prefix(hash: &[u8; 16], bits: u32) -> u64 {
+  if bits == 0 || bits > 64 {
+    panic!("A hash prefix must be between 1 and 64 bits, not {}", bits);
+  }
+  let first = hash[..8].iter().fold(0, |h, &byte| h << 8 | byte as u64);
+  first >> (64 - bits)
+}
+
+/// Number of candidates hashed in parallel at a time by the searches.
+const SEARCH_BATCH: u64 = 4096;
+
+/// The `i`th candidate input tried by the searches.
+fn search_input(salt: &str, i: u64) -> String {
+  format!("{}{}", salt, i)
+}
+
+fn search_batch(salt: &str, bits: u32, start: u64, end: u64) -> Vec<u64> {
+  (start..end).into_par_iter()
+    .map(|i| hash_prefix(&standard_hash(search_input(salt, i).as_bytes()), bits))
+    .collect()
+}
+
+/// How a search for a collision or preimage went. `hashes` counts candidates
+/// up to and including the one that succeeded, even though the rest of its
+/// batch was hashed too, so that it can be compared with the expected work.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Search {
+  pub hashes: u64,
+  /// The inputs found: two colliding inputs, or a preimage alone.
+  pub found: Vec<String>,
+}
 
-  let hash: Vec<String> = sparse_hash.chunks(16)
-  .map(|block| {
-    let mut block_iter = block.iter();
-    let initial = *block_iter.next().unwrap();
-    format!("{:02x}", block_iter.fold(initial, |h, i| h ^ i))
-  })
-  .collect();
+/// Expected number of hashes before two of them share their first `bits`
+/// bits, by the birthday bound.
+pub fn expected_collision_work(bits: u32) -> f64 {
+  (PI / 2.0 * 2f64.powi(bits as i32)).sqrt()
+}
+
+/// Expected number of hashes before one starts with a given `bits` bits.
+pub fn expected_preimage_work(bits: u32) -> f64 {
+  2f64.powi(bits as i32)
+}
+
+/// Hashes `salt` followed by 0, 1, 2, ... until two hashes agree on their
+/// first `bits` bits, or `max_hashes` candidates have been tried.
+pub fn find_collision(bits: u32, salt: &str, max_hashes: u64) -> Search {
+  let mut seen: HashMap<u64, u64> = HashMap::new();
+  let mut start = 0;
+
+  while start < max_hashes {
+    let end = max_hashes.min(start + SEARCH_BATCH);
+    for (i, prefix) in (start..end).zip(search_batch(salt, bits, start, end)) {
+      if let Some(&earlier) = seen.get(&prefix) {
+        return Search { hashes: i + 1, found: vec![search_input(salt, earlier), search_input(salt, i)] };
+      }
+      seen.insert(prefix, i);
+    }
+    start = end;
+  }
 
-  hash.as_slice().join("")
+  Search { hashes: max_hashes, found: vec![] }
+}
+
+/// Hashes `salt` followed by 0, 1, 2, ... until a hash's first `bits` bits
+/// are `target`, or `max_hashes` candidates have been tried.
+pub fn find_preimage(target: u64, bits: u32, salt: &str, max_hashes: u64) -> Search {
+  let mut start = 0;
+
+  while start < max_hashes {
+    let end = max_hashes.min(start + SEARCH_BATCH);
+    let hit = (start..end).into_par_iter()
+      .find_first(|&i| hash_prefix(&standard_hash(search_input(salt, i).as_bytes()), bits) == target);
+    if let Some(i) = hit {
+      return Search { hashes: i + 1, found: vec![search_input(salt, i)] };
+    }
+    start = end;
+  }
+
+  Search { hashes: max_hashes, found: vec![] }
+}
+
+pub fn knot_hash(input: String) -> String {
+  let mut hasher = KnotHasher::new();
+  hasher.update(input.as_bytes());
+  to_hex(&hasher.finalize())
 }
 
 pub fn main() {
   let lengths: &[usize] = &[147, 37, 249, 1, 31, 2, 226, 0, 161, 71, 254, 243, 183, 255, 30, 70];
-  let hash_bytes = hash_slice(lengths, 1);
+  let hash_bytes = KnotHashParams::new().rounds(1).suffix(&[]).sparse_hash(lengths);
   println!("First two numbers are {} and {}. Their product is {}", hash_bytes[0], hash_bytes[1], hash_bytes[0] as u16 * hash_bytes[1] as u16);
 
   let str_input = String::from("147,37,249,1,31,2,226,0,161,71,254,243,183,255,30,70");
@@ -75,38 +298,116 @@ mod test {
 
   #[test]
   fn compute_extended_lengths_works() {
-    assert_eq!(vec![49,44,50,44,51,17,31,73,47,23], compute_extended_lengths("1,2,3".to_string()));
+    assert_eq!(vec![49,44,50,44,51,17,31,73,47,23], compute_extended_lengths(b"1,2,3", &STANDARD_SUFFIX));
+  }
+
+  #[test]
+  fn knot_hash_works() {
+    assert_eq!("a2582a3a0e66e6e86e3812dcb672a272", knot_hash(String::from("")));
+    assert_eq!("3efbe78a8d82f29979031a4aa0b16a9d", knot_hash(String::from("1,2,3")));
+    assert_eq!("63960835bcdc130f0b66d7ff4f6a5a8e", knot_hash(String::from("1,2,4")));
+    assert_eq!("33efeb34ea91902bb2f59c9920caa6cd", knot_hash(String::from("AoC 2017")));
   }
 
   #[test]
-  fn reverse_slice_segment_works_for_linear_spans_with_odd_length() {
-    let slice = &[1, 2, 3, 4, 5, 6, 7, 8];
-    assert_eq!(vec![3, 2, 1], reverse_slice_segment(slice, 0, 3));
+  fn knot_hasher_accepts_input_in_pieces() {
+    let mut hasher = KnotHasher::new();
+    hasher.update(b"AoC ");
+    hasher.update(b"2017");
+    let hash = hasher.finalize();
+
+    assert_eq!("33efeb34ea91902bb2f59c9920caa6cd", to_hex(&hash));
+    assert_eq!(0x33, hash[0]);
+    assert_eq!(0xcd, hash[15]);
   }
 
   #[test]
-  fn reverse_slice_segment_works_for_circular_spans_with_odd_length() {
-    let slice = &[0, 1, 2, 3, 4, 5];
-    assert_eq!(vec![1,0,5], reverse_slice_segment(slice, 5, 3));
+  fn knot_hasher_finish_is_the_first_half() {
+    let mut hasher = KnotHasher::new();
+    hasher.write(b"1,2,3");
+    assert_eq!(0x3efbe78a8d82f299, hasher.finish());
   }
 
   #[test]
-  fn reverse_slice_segment_works_for_linear_spans_with_even_length() {
-    let slice = &[1, 2, 3, 4, 5, 6, 7, 8];
-    assert_eq!(vec![2, 1], reverse_slice_segment(slice, 0, 2));
+  fn to_bits_works() {
+    let bits = to_bits(&[0xa0, 0x01]);
+    assert_eq!(16, bits.len());
+    assert_eq!(vec![true, false, true, false, false, false, false, false], &bits[..8]);
+    assert_eq!(vec![false, false, false, false, false, false, false, true], &bits[8..]);
   }
 
   #[test]
-  fn reverse_slice_segment_works_for_circular_spans_with_even_length() {
-    let slice = &[0, 1, 2, 3, 4, 5];
-    assert_eq!(vec![2, 1, 0, 5], reverse_slice_segment(slice, 5, 4));
+  fn knot_hash_params_run_the_five_element_example() {
+    let params = KnotHashParams::new().ring_size(5).rounds(1).suffix(&[]);
+    assert_eq!(vec![3, 4, 2, 1, 0], params.sparse_hash(&[3, 4, 1, 5]));
+    assert_eq!(vec![4], params.clone().block_size(5).hash(&[3, 4, 1, 5]));
+    assert_eq!(vec![7, 3, 0], params.block_size(2).hash(&[3, 4, 1, 5]));
   }
 
   #[test]
-  fn knot_hash_works() {
-    assert_eq!("a2582a3a0e66e6e86e3812dcb672a272", knot_hash(String::from("")));
-    assert_eq!("3efbe78a8d82f29979031a4aa0b16a9d", knot_hash(String::from("1,2,3")));
-    assert_eq!("63960835bcdc130f0b66d7ff4f6a5a8e", knot_hash(String::from("1,2,4")));
-    assert_eq!("33efeb34ea91902bb2f59c9920caa6cd", knot_hash(String::from("AoC 2017")));
+  fn knot_hash_params_default_to_the_standard_hash() {
+    let hash = KnotHashParams::new().hash(b"1,2,3");
+    assert_eq!("3efbe78a8d82f29979031a4aa0b16a9d", to_hex(&hash));
+  }
+
+  #[test]
+  fn knot_hash_params_run_custom_variants() {
+    let small = KnotHashParams::new().ring_size(16).rounds(8).suffix(&[3, 1]).block_size(4);
+    assert_eq!("0f03030f", to_hex(&small.hash(&[5, 0, 16, 2])));
+
+    let plain = KnotHashParams::new().rounds(16).suffix(&[]).block_size(32);
+    assert_eq!("e273ed76cf473dbf", to_hex(&plain.hash(b"AoC 2017")));
+  }
+
+  #[test]
+  #[should_panic(expected = "Length 6 is longer than the ring of 5 elements")]
+  fn knot_hash_params_reject_long_lengths() {
+    KnotHashParams::new().ring_size(5).suffix(&[]).sparse_hash(&[6]);
+  }
+
+  #[test]
+  fn knot_hash_batch_matches_knot_hasher() {
+    let keys: Vec<String> = (0..20).map(|row| format!("flqrgnkx-{}", row)).collect();
+    let hashes = knot_hash_batch(&keys);
+
+    assert_eq!(20, hashes.len());
+    for (key, hash) in keys.iter().zip(&hashes) {
+      let mut hasher = KnotHasher::new();
+      hasher.update(key.as_bytes());
+      assert_eq!(hasher.finalize(), *hash);
+    }
+  }
+
+  #[test]
+  fn hash_prefix_works() {
+    let mut hash = [0; 16];
+    hash[0] = 0xa5;
+    hash[1] = 0xff;
+    assert_eq!(0xa, hash_prefix(&hash, 4));
+    assert_eq!(0xa5f, hash_prefix(&hash, 12));
+    assert_eq!(0xa5ff << 48, hash_prefix(&hash, 64));
+  }
+
+  #[test]
+  fn find_collision_finds_a_shared_prefix() {
+    let search = find_collision(12, "collide-", 10_000);
+    assert_eq!(2, search.found.len());
+    assert_ne!(search.found[0], search.found[1]);
+    assert_eq!(search.found[1], format!("collide-{}", search.hashes - 1));
+
+    let hashes = knot_hash_batch(&search.found);
+    assert_eq!(hash_prefix(&hashes[0], 12), hash_prefix(&hashes[1], 12));
+  }
+
+  #[test]
+  fn find_preimage_matches_the_target_prefix() {
+    let target = hash_prefix(&knot_hash_batch(&["target"])[0], 8);
+    let search = find_preimage(target, 8, "pre-", 10_000);
+    assert_eq!(1, search.found.len());
+    assert_eq!(target, hash_prefix(&knot_hash_batch(&search.found)[0], 8));
+
+    let limited = find_preimage(target, 8, "pre-", search.hashes - 1);
+    assert_eq!(search.hashes - 1, limited.hashes);
+    assert!(limited.found.is_empty());
   }
 }
@@ -1,7 +1,8 @@
-use std::collections::VecDeque;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::io::Write;
 use std::str::Lines;
-use self::TapeValue::*;
+use common;
 use self::Direction::*;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -9,30 +10,159 @@ enum Direction {
   Left, Right
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum TapeValue {
-  One, Zero
-}
+/// A tape symbol, stored as an index into the machine's alphabet. The blank
+/// symbol is always 0, so freshly allocated tape is blank.
+type Symbol = usize;
 
 struct StateBranch {
-  write: TapeValue,
+  write: Symbol,
   move_dir: Direction,
-  transition: String,
+  /// Index of the next state in `TuringMachine::states`, or `None` to halt
+  /// once the value is written and the head has moved.
+  transition: Option<usize>,
 }
 
 struct State {
   name: String,
-  zero_branch: StateBranch,
-  one_branch: StateBranch,
+  /// One branch per symbol of the alphabet, indexed by the symbol under the cursor.
+  branches: Vec<StateBranch>,
+}
+
+/// A `StateBranch` as laid out for the run loop.
+struct Transition {
+  write: u64,
+  right: bool,
+  /// The next state's first row in the table, or `HALT_ROW`.
+  next: usize,
+}
+
+const HALT_ROW: usize = usize::MAX;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Outcome {
+  /// The machine took a halting branch, after this many steps in total.
+  Halted(usize),
+  /// The machine was still running when the step limit was reached.
+  StepLimit,
+}
+
+/// Running and checksumming, for every kind of machine a spec can describe.
+pub trait Machine {
+  /// The step count from the spec's "Perform a diagnostic checksum" line.
+  fn max_steps(&self) -> usize;
+  /// Steps taken so far.
+  fn steps(&self) -> usize;
+  /// Runs the machine for up to `steps` more steps, stopping early if it halts.
+  fn run(&mut self, steps: usize) -> Outcome;
+  /// Counts the cells holding anything other than the blank symbol.
+  fn checksum(&self) -> usize;
+
+  fn run_to_max_steps(&mut self) -> Outcome {
+    let remaining = self.max_steps().saturating_sub(self.steps());
+    self.run(remaining)
+  }
+}
+
+/// A branch as written in the spec, before state names are resolved to indices.
+struct BranchSpec {
+  read: Symbol,
+  write: Symbol,
+  move_dir: Direction,
+  /// `None` for a halting branch.
+  transition: Option<String>,
+}
+
+/// Number of words added to either end of the tape when the head runs off it,
+/// at minimum. The tape grows by at least its current size, so growth is
+/// amortized O(1) no matter which end the head favours.
+const TAPE_CHUNK_WORDS: usize = 64;
+
+/// A tape of fixed-width cells bit-packed into `u64` words. Each cell is
+/// 1, 2, 4 or 8 bits wide, whichever is the smallest that fits the alphabet.
+struct Tape {
+  words: Vec<u64>,
+  /// log2 of the cell width in bits.
+  cell_shift: u32,
+  /// log2 of the number of cells per word.
+  word_shift: u32,
+}
+
+impl Tape {
+  fn new(symbols: usize) -> Self {
+    let cell_shift = match symbols {
+      0..=2 => 0,
+      3..=4 => 1,
+      5..=16 => 2,
+      17..=256 => 3,
+      _ => panic!("Alphabets of more than 256 symbols are not supported"),
+    };
+
+    Tape {
+      words: vec![0; TAPE_CHUNK_WORDS],
+      cell_shift,
+      word_shift: 6 - cell_shift,
+    }
+  }
+
+  fn len(&self) -> usize {
+    self.words.len() << self.word_shift
+  }
+
+  #[inline]
+  fn get(&self, cell: usize) -> Symbol {
+    let word = self.words[cell >> self.word_shift];
+    let offset = (cell & ((1 << self.word_shift) - 1)) << self.cell_shift;
+    let mask = (1u64 << (1 << self.cell_shift)) - 1;
+    ((word >> offset) & mask) as Symbol
+  }
+
+  fn grow_amount(&self) -> usize {
+    self.words.len().max(TAPE_CHUNK_WORDS)
+  }
+
+  /// Prepends blank words to the tape, returning how many cells were added.
+  fn grow_front(&mut self) -> usize {
+    let added = self.grow_amount();
+    let mut words = vec![0; added + self.words.len()];
+    words[added..].copy_from_slice(&self.words);
+    self.words = words;
+    added << self.word_shift
+  }
+
+  fn grow_back(&mut self) {
+    let added = self.grow_amount();
+    let len = self.words.len();
+    self.words.resize(len + added, 0);
+  }
 }
 
-struct TuringMachine {
-  tape: VecDeque<TapeValue>,
+pub struct TuringMachine {
+  alphabet: Vec<String>,
+  blank: Symbol,
+  tape: Tape,
   cursor: usize,
-  curr_state: String,
+  /// Leftmost and rightmost cells the head has visited, as tape indices.
+  visited: (usize, usize),
+  /// Tape index of the cell the head started on.
+  origin: usize,
+  /// The state the machine began in, for writing its spec back out.
+  start: usize,
+  curr_state: usize,
   max_steps: usize,
   curr_steps: usize,
-  states: HashMap<String, State>,
+  /// The state and symbol whose branch halted the machine, once it has.
+  halted: Option<(usize, Symbol)>,
+  states: Vec<State>,
+}
+
+fn intern_symbol(alphabet: &mut Vec<String>, raw: &str) -> Symbol {
+  match alphabet.iter().position(|sym| sym == raw) {
+    Some(symbol) => symbol,
+    None => {
+      alphabet.push(raw.to_string());
+      alphabet.len() - 1
+    }
+  }
 }
 
 fn parse_initial_state(lines: &mut Lines) -> String {
@@ -46,17 +176,22 @@ fn parse_max_steps(lines: &mut Lines) -> usize {
   words.nth(5).unwrap().parse().unwrap()
 }
 
-fn parse_states(lines: &mut Lines) -> HashMap<String, State> {
-  let mut states = HashMap::new();
+/// A state's name and its branches, as parsed from one block of a spec.
+type StateSpec<B> = (String, Vec<B>);
+
+/// Splits the rest of the spec into state blocks, and parses each one with
+/// `parse_state`.
+fn parse_states<B>(lines: &mut Lines, alphabet: &mut Vec<String>, parse_state: fn(&[&str], &mut Vec<String>) -> StateSpec<B>) -> Vec<StateSpec<B>> {
+  let mut specs = Vec::new();
 
   loop {
-    let mut state_buf = Vec::with_capacity(9);
+    let mut state_buf = Vec::new();
 
     loop {
       let ln = lines.next();
       match ln {
         None => break,
-        Some(line) => if line == "" {
+        Some(line) => if line.is_empty() {
           break;
         }
         else {
@@ -65,26 +200,275 @@ fn parse_states(lines: &mut Lines) -> HashMap<String, State> {
       }
     }
 
-    if state_buf.len() == 9 {
-      let state = State::from(state_buf);
-      states.insert(state.name.clone(), state);
+    if state_buf.is_empty() {
+      break;
+    }
+    specs.push(parse_state(&state_buf, alphabet));
+  }
+
+  specs
+}
+
+/// Resolves state names to indices and places each state's branches in
+/// alphabet order. Every symbol is only known once all states are parsed.
+fn compile_states(specs: Vec<(String, Vec<BranchSpec>)>, alphabet: &[String]) -> Vec<State> {
+  let indices = state_indices(&specs);
+
+  specs.into_iter().map(|(name, mut spec)| {
+    let branches = (0..alphabet.len()).map(|symbol| {
+      let branch = match spec.iter().position(|branch| branch.read == symbol) {
+        Some(i) => spec.swap_remove(i),
+        None => panic!("State {} has no branch for value {}", name, alphabet[symbol]),
+      };
+      StateBranch {
+        write: branch.write,
+        move_dir: branch.move_dir,
+        transition: resolve_transition(&indices, &name, branch.transition),
+      }
+    }).collect();
+
+    State { name, branches }
+  }).collect()
+}
+
+fn state_indices<B>(specs: &[(String, Vec<B>)]) -> HashMap<String, usize> {
+  specs.iter().enumerate()
+    .map(|(i, (name, _))| (name.clone(), i))
+    .collect()
+}
+
+fn resolve_transition(indices: &HashMap<String, usize>, state: &str, next: Option<String>) -> Option<usize> {
+  next.map(|next| match indices.get(&next) {
+    Some(&i) => i,
+    None => panic!("State {} continues with unknown state {}", state, next),
+  })
+}
+
+fn find_state<'a>(mut names: impl Iterator<Item=&'a String>, name: &str) -> usize {
+  match names.position(|state| state == name) {
+    Some(i) => i,
+    None => panic!("Initial state {} is not defined", name),
+  }
+}
+
+/// States are lettered `A` to `Y` in standard notation; `Z` means halt.
+fn state_letter(index: usize) -> String {
+  if index >= 25 {
+    panic!("Standard notation only has letters for 25 states");
+  }
+  ((b'A' + index as u8) as char).to_string()
+}
+
+/// Quotes `raw` as a TOML or JSON string, which escape the same way.
+fn quote(raw: &str) -> String {
+  let mut quoted = String::from("\"");
+  for ch in raw.chars() {
+    match ch {
+      '"' => quoted.push_str("\\\""),
+      '\\' => quoted.push_str("\\\\"),
+      '\n' => quoted.push_str("\\n"),
+      '\r' => quoted.push_str("\\r"),
+      '\t' => quoted.push_str("\\t"),
+      ch if ch.is_control() => quoted.push_str(&format!("\\u{:04x}", ch as u32)),
+      ch => quoted.push(ch),
+    }
+  }
+  quoted.push('"');
+  quoted
+}
+
+/// Reads the quoted string at the start of `input`, undoing the escapes that
+/// `quote` writes, and returns it with the rest of the input.
+fn parse_quoted(input: &str) -> (String, &str) {
+  let mut chars = input.char_indices();
+  if chars.next().map(|(_, ch)| ch) != Some('"') {
+    panic!("Expected a quoted string: {}", input);
+  }
+
+  let mut value = String::new();
+  while let Some((i, ch)) = chars.next() {
+    match ch {
+      '"' => return (value, &input[i + 1..]),
+      '\\' => value.push(match chars.next().map(|(_, ch)| ch) {
+        Some('"') => '"',
+        Some('\\') => '\\',
+        Some('n') => '\n',
+        Some('r') => '\r',
+        Some('t') => '\t',
+        Some('u') => {
+          let hex: String = chars.by_ref().take(4).map(|(_, ch)| ch).collect();
+          let code = if hex.len() == 4 && hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+            u32::from_str_radix(&hex, 16).ok().and_then(std::char::from_u32)
+          }
+          else {
+            None
+          };
+          match code {
+            Some(ch) => ch,
+            None => panic!("Invalid unicode escape in quoted string: {}", input),
+          }
+        },
+        _ => panic!("Unsupported escape in quoted string: {}", input),
+      }),
+      ch => value.push(ch),
+    }
+  }
+
+  panic!("Unterminated quoted string: {}", input)
+}
+
+/// Reads a bare or quoted TOML key at the start of `input`, returning it with
+/// the rest of the input.
+fn parse_toml_key(input: &str) -> (String, &str) {
+  let input = input.trim_start();
+  if input.starts_with('"') {
+    return parse_quoted(input);
+  }
+
+  let end = input.find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_' || ch == '-')).unwrap_or(input.len());
+  if end == 0 {
+    panic!("Unsupported TOML key: {}", input);
+  }
+  (input[..end].to_string(), &input[end..])
+}
+
+/// Reads an array of quoted strings at the start of `input`, returning it with
+/// the rest of the input.
+fn parse_toml_array(input: &str) -> (Vec<String>, &str) {
+  let mut rest = match input.trim_start().strip_prefix('[') {
+    Some(rest) => rest,
+    None => panic!("Expected an array of strings: {}", input),
+  };
+
+  let mut values = Vec::new();
+  loop {
+    let (value, after) = parse_quoted(rest.trim_start());
+    values.push(value);
+    let after = after.trim_start();
+    if let Some(after) = after.strip_prefix(',') {
+      rest = after;
+    }
+    else if let Some(after) = after.strip_prefix(']') {
+      return (values, after);
     }
     else {
-      break;
+      panic!("Expected , or ] in array: {}", input);
     }
   }
+}
 
-  states
+/// Checks nothing but a comment follows a TOML value.
+fn expect_toml_end(rest: &str, line: &str) {
+  let rest = rest.trim();
+  if !rest.is_empty() && !rest.starts_with('#') {
+    panic!("Unsupported TOML after the value: {}", line);
+  }
 }
 
-fn parse_state_branch_from_iterator(iter: &mut Iterator<Item=&&str>) -> StateBranch {
-  let write_raw = iter.next().unwrap().split_whitespace().nth(4).unwrap();
-  let write = match write_raw {
-    "1." => One,
-    "0." => Zero,
-    _ => panic!("Got invalid write for a state: {}", write_raw),
+/// A JSON value, as far as the table format needs one. Objects keep their
+/// keys in the order they were written, since that orders the states.
+enum Json {
+  Str(String),
+  Num(usize),
+  Arr(Vec<Json>),
+  Obj(Vec<(String, Json)>),
+}
+
+/// Reads the JSON value at the start of `input`, returning it with the rest of
+/// the input. Only strings, whole numbers, arrays and objects are understood.
+fn parse_json(input: &str) -> (Json, &str) {
+  let input = input.trim_start();
+  let close = match input.chars().next() {
+    Some('"') => {
+      let (value, rest) = parse_quoted(input);
+      return (Json::Str(value), rest);
+    },
+    Some('0'..='9') => {
+      let end = input.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(input.len());
+      match input[..end].parse() {
+        Ok(num) => return (Json::Num(num), &input[end..]),
+        Err(_) => panic!("Invalid JSON number: {}", &input[..end]),
+      }
+    },
+    Some('[') => ']',
+    Some('{') => '}',
+    _ => panic!("Unsupported JSON value: {}", input.lines().next().unwrap_or("")),
   };
 
+  let mut items = Vec::new();
+  let mut rest = input[1..].trim_start();
+  if let Some(after) = rest.strip_prefix(close) {
+    rest = after;
+  }
+  else {
+    loop {
+      let key = if close == '}' {
+        let (key, after) = parse_quoted(rest.trim_start());
+        rest = match after.trim_start().strip_prefix(':') {
+          Some(after) => after,
+          None => panic!("Expected : after JSON key {}", key),
+        };
+        key
+      }
+      else {
+        String::new()
+      };
+
+      let (value, after) = parse_json(rest);
+      items.push((key, value));
+      let after = after.trim_start();
+      if let Some(after) = after.strip_prefix(',') {
+        rest = after;
+      }
+      else if let Some(after) = after.strip_prefix(close) {
+        rest = after;
+        break;
+      }
+      else {
+        panic!("Expected , or {} in JSON: {}", close, after.lines().next().unwrap_or(""));
+      }
+    }
+  }
+
+  if close == '}' {
+    (Json::Obj(items), rest)
+  }
+  else {
+    (Json::Arr(items.into_iter().map(|(_, value)| value).collect()), rest)
+  }
+}
+
+/// A branch from the TOML or JSON table format: `fields` are the value to
+/// write, the move and, unless the branch halts, the next state.
+fn parse_table_branch(state: &str, read: &str, fields: &[String], alphabet: &mut Vec<String>, branches: &[BranchSpec]) -> BranchSpec {
+  if fields.len() != 2 && fields.len() != 3 {
+    panic!("State {} has a malformed branch for value {}", state, read);
+  }
+
+  let read_symbol = intern_symbol(alphabet, read);
+  if branches.iter().any(|branch| branch.read == read_symbol) {
+    panic!("State {} has more than one branch for value {}", state, read);
+  }
+  BranchSpec {
+    read: read_symbol,
+    write: intern_symbol(alphabet, &fields[0]),
+    move_dir: match fields[1].as_str() {
+      "L" => Left,
+      "R" => Right,
+      dir => panic!("Got invalid move direction for a state, {}", dir),
+    },
+    transition: fields.get(2).cloned(),
+  }
+}
+
+fn strip_period(word: &str) -> &str {
+  word.trim_end_matches('.').trim_end_matches(':')
+}
+
+fn parse_state_branch_from_iterator(iter: &mut dyn Iterator<Item=&&str>, read: Symbol, alphabet: &mut Vec<String>) -> BranchSpec {
+  let write_raw = iter.next().unwrap().split_whitespace().nth(4).unwrap();
+  let write = intern_symbol(alphabet, strip_period(write_raw));
+
   let move_dir_raw = iter.next().unwrap().split_whitespace().nth(6).unwrap();
   let move_dir = match move_dir_raw {
     "left." => Left,
@@ -92,212 +476,2443 @@ fn parse_state_branch_from_iterator(iter: &mut Iterator<Item=&&str>) -> StateBra
     _ => panic!("Got invalid move direction for a state, {}", move_dir_raw),
   };
 
-  let transition_raw = iter.next().unwrap().split_whitespace().nth(4).unwrap();
-  let transition = transition_raw[..transition_raw.len() - 1].to_string();
+  let transition_line = iter.next().unwrap();
+  let transition = if transition_line.trim() == "- Halt." {
+    None
+  }
+  else {
+    let transition_raw = transition_line.split_whitespace().nth(4).unwrap();
+    Some(strip_period(transition_raw).to_string())
+  };
 
-  StateBranch {
-    write, move_dir, transition,
+  BranchSpec {
+    read, write, move_dir, transition,
   }
 }
 
-impl State {
-  fn from(spec: Vec<&str>) -> Self {
-    let mut iter = spec.iter();
-    let name_w = iter.next().unwrap().split_whitespace().nth(2).unwrap();
-    let name = name_w[..name_w.len() - 1].to_string();
-
-    iter.next(); // we assume we're in the zero section
-    let zero_branch = parse_state_branch_from_iterator(&mut iter);
-
-    iter.next(); // we assume we're in the one section
-    let one_branch = parse_state_branch_from_iterator(&mut iter);
+/// Parses a state block into its name and a branch for each "If the current
+/// value is" section, in the order they appear.
+fn parse_state_spec(spec: &[&str], alphabet: &mut Vec<String>) -> (String, Vec<BranchSpec>) {
+  let mut iter = spec.iter();
+  let name_w = iter.next().unwrap().split_whitespace().nth(2).unwrap();
+  let name = strip_period(name_w).to_string();
 
-    State {
-      name, zero_branch, one_branch
+  let mut branches: Vec<BranchSpec> = Vec::new();
+  while let Some(line) = iter.next() {
+    let read_raw = line.split_whitespace().nth(5).unwrap();
+    let read = intern_symbol(alphabet, strip_period(read_raw));
+    if branches.iter().any(|branch| branch.read == read) {
+      panic!("State {} has more than one branch for value {}", name, alphabet[read]);
     }
+
+    branches.push(parse_state_branch_from_iterator(&mut iter, read, alphabet));
   }
+
+  (name, branches)
+}
+
+/// The preamble line naming the blank symbol, which puzzle inputs leave out.
+const BLANK_LINE: &str = "The tape starts out filled with the value ";
+
+/// Reads the optional blank line that follows the checksum line, consuming
+/// the empty line after the preamble either way.
+fn parse_blank(lines: &mut Lines) -> Option<String> {
+  let blank = lines.next()?.strip_prefix(BLANK_LINE)?;
+  lines.next();
+  Some(strip_period(blank).to_string())
 }
 
 impl TuringMachine {
-  fn from(spec: &str) -> Self {
+  /// Parses a machine in the puzzle's format, whose blank symbol is 0 unless
+  /// the spec names another one after the checksum line.
+  pub fn from(spec: &str) -> Self {
+    TuringMachine::with_blank(spec, "0")
+  }
+
+  /// Parses a machine whose tape starts out filled with `blank`, unless the
+  /// spec names its own blank symbol. The alphabet is every value read or
+  /// written by the spec, plus the blank symbol.
+  pub fn with_blank(spec: &str, blank: &str) -> Self {
     let mut lines = spec.lines();
 
-    let curr_state = parse_initial_state(&mut lines);
+    let initial_state = parse_initial_state(&mut lines);
     let max_steps = parse_max_steps(&mut lines);
+    let blank = parse_blank(&mut lines).unwrap_or_else(|| blank.to_string());
 
-    // discard the blank line between preamble and first state
-    lines.next();
-    let states = parse_states(&mut lines);
+    let mut alphabet = vec![blank];
+    let specs = parse_states(&mut lines, &mut alphabet, parse_state_spec);
+    let states = compile_states(specs, &alphabet);
 
-    let mut tape = VecDeque::new();
-    tape.push_back(Zero);
+    let curr_state = find_state(states.iter().map(|state| &state.name), &initial_state);
 
-    TuringMachine {
-      tape,
-      cursor: 0,
-      curr_state,
-      max_steps,
-      curr_steps: 0,
-      states,
+    TuringMachine::new(alphabet, states, curr_state, max_steps)
+  }
+
+  /// Parses the compact notation written by `to_standard_notation`, such as
+  /// `1RB1LB_1LA1RZ`. Symbols are the digits `0` up to the number of branches
+  /// per state, with `0` blank. A `---` transition, which the busy beaver
+  /// community uses for one that is never taken, is read as `1RZ`. There's no
+  /// step count in the notation, so `max_steps` is 0.
+  pub fn from_standard_notation(notation: &str) -> Self {
+    let groups: Vec<&[u8]> = notation.trim().split('_').map(str::as_bytes).collect();
+    let symbols = groups[0].len() / 3;
+    if symbols == 0 || symbols > 10 || groups.iter().any(|group| group.len() != 3 * symbols) {
+      panic!("Invalid standard notation: {}", notation);
     }
+
+    let states = groups.iter().enumerate().map(|(i, group)| {
+      let branches = group.chunks(3).map(|t| {
+        if t == b"---" {
+          return StateBranch { write: 1, move_dir: Right, transition: None };
+        }
+
+        let write = match t[0] {
+          b'0'..=b'9' if ((t[0] - b'0') as usize) < symbols => (t[0] - b'0') as Symbol,
+          _ => panic!("Invalid value to write in standard notation: {}", t[0] as char),
+        };
+        let move_dir = match t[1] {
+          b'L' => Left,
+          b'R' => Right,
+          _ => panic!("Invalid move direction in standard notation: {}", t[1] as char),
+        };
+        let transition = match t[2] {
+          b'Z' | b'H' => None,
+          b'A'..=b'Y' if ((t[2] - b'A') as usize) < groups.len() => Some((t[2] - b'A') as usize),
+          _ => panic!("Invalid next state in standard notation: {}", t[2] as char),
+        };
+
+        StateBranch { write, move_dir, transition }
+      }).collect();
+
+      State { name: state_letter(i), branches }
+    }).collect();
+
+    let alphabet = (0..symbols).map(|symbol| symbol.to_string()).collect();
+    TuringMachine::new(alphabet, states, 0, 0)
   }
 
-  fn run_to_max_steps(&mut self) {
-    while self.curr_steps < self.max_steps {
-      self.curr_steps += 1;
+  /// Parses the TOML table format written by `to_toml`. Only the subset of
+  /// TOML that format uses is understood: `start`, `steps` and `blank` keys,
+  /// then a `[states.NAME]` table per state mapping each value read to a
+  /// `[write, move, next]` array, or `[write, move]` for a halting branch.
+  /// Strings must be double-quoted, and anything else is rejected.
+  pub fn from_toml(spec: &str) -> Self {
+    let mut initial_state = None;
+    let mut max_steps = 0;
+    let mut alphabet = vec!["0".to_string()];
+    let mut specs: Vec<(String, Vec<BranchSpec>)> = Vec::new();
 
-      let state = self.states.get(&self.curr_state).unwrap();
+    for line in spec.lines().map(str::trim) {
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
 
-      let tape_val = self.tape[self.cursor];
+      if let Some(header) = line.strip_prefix('[') {
+        let (table, rest) = parse_toml_key(header);
+        let name = match rest.strip_prefix('.') {
+          Some(rest) if table == "states" => parse_toml_key(rest),
+          _ => panic!("Unknown TOML table: {}", line),
+        };
+        match name.1.trim_start().strip_prefix(']') {
+          Some(rest) => expect_toml_end(rest, line),
+          None => panic!("Unsupported TOML table header: {}", line),
+        }
+        specs.push((name.0, Vec::new()));
+        continue;
+      }
 
-      let branch = match tape_val {
-        Zero => &state.zero_branch, One => &state.one_branch,
+      let (key, rest) = parse_toml_key(line);
+      let value = match rest.trim_start().strip_prefix('=') {
+        Some(value) => value.trim_start(),
+        None => panic!("Expected a key and value in TOML line: {}", line),
       };
 
-      // write the value to tape
-      self.tape[self.cursor] = branch.write;
-
-      // move left or right
-      match branch.move_dir {
-        Left => {
-          // if cursor is 0, push_front
-          if self.cursor == 0 {
-            self.tape.push_front(Zero);
-          }
-          else {
-            self.cursor -= 1;
-          }
+      // top-level keys all come before the first table, so the blank symbol
+      // is known before any value is interned
+      match specs.last_mut() {
+        None => match key.as_str() {
+          "start" | "blank" => {
+            let (value, rest) = parse_quoted(value);
+            expect_toml_end(rest, line);
+            if key == "start" {
+              initial_state = Some(value);
+            }
+            else {
+              alphabet[0] = value;
+            }
+          },
+          "steps" => {
+            let end = value.find(|ch: char| !ch.is_ascii_digit()).unwrap_or(value.len());
+            max_steps = match value[..end].parse() {
+              Ok(steps) => steps,
+              Err(_) => panic!("Invalid step count in TOML line: {}", line),
+            };
+            expect_toml_end(&value[end..], line);
+          },
+          _ => panic!("Unknown TOML key: {}", key),
         },
-        Right => {
-          // if cursor is at the end, push_back
-          if self.cursor == self.tape.len() - 1 {
-            self.tape.push_back(Zero);
-          }
-          self.cursor += 1;
+        Some(&mut (ref name, ref mut branches)) => {
+          let (fields, rest) = parse_toml_array(value);
+          expect_toml_end(rest, line);
+          let branch = parse_table_branch(name, &key, &fields, &mut alphabet, branches);
+          branches.push(branch);
         },
       }
+    }
+
+    TuringMachine::from_table(alphabet, specs, initial_state, max_steps)
+  }
+
+  /// Parses the JSON form of the table format, as written by `to_json`: an
+  /// object with `start`, `steps` and `blank` keys, and a `states` object
+  /// mapping each state's name to its branches, as in `from_toml`.
+  pub fn from_json(spec: &str) -> Self {
+    let (json, rest) = parse_json(spec);
+    if !rest.trim().is_empty() {
+      panic!("Unexpected text after JSON: {}", rest.trim());
+    }
+    let fields = match json {
+      Json::Obj(fields) => fields,
+      _ => panic!("Expected a JSON object"),
+    };
 
-      // transition state
-      self.curr_state = branch.transition.clone();
+    let mut initial_state = None;
+    let mut max_steps = 0;
+    let mut alphabet = vec!["0".to_string()];
+    let mut states = Vec::new();
+    for (key, value) in fields {
+      match (key.as_str(), value) {
+        ("start", Json::Str(name)) => initial_state = Some(name),
+        ("steps", Json::Num(steps)) => max_steps = steps,
+        ("blank", Json::Str(blank)) => alphabet[0] = blank,
+        ("states", Json::Obj(table)) => states = table,
+        (key, _) => panic!("Unknown or malformed JSON key: {}", key),
+      }
     }
+
+    // the blank symbol may come after the states, so they're read last
+    let specs = states.into_iter().map(|(name, table)| {
+      let table = match table {
+        Json::Obj(table) => table,
+        _ => panic!("State {} should be a JSON object", name),
+      };
+
+      let mut branches = Vec::new();
+      for (read, fields) in table {
+        let fields: Vec<String> = match fields {
+          Json::Arr(fields) => fields.into_iter().map(|field| match field {
+            Json::Str(field) => field,
+            _ => panic!("State {} has a malformed branch for value {}", name, read),
+          }).collect(),
+          _ => panic!("State {} has a malformed branch for value {}", name, read),
+        };
+        let branch = parse_table_branch(&name, &read, &fields, &mut alphabet, &branches);
+        branches.push(branch);
+      }
+      (name, branches)
+    }).collect();
+
+    TuringMachine::from_table(alphabet, specs, initial_state, max_steps)
   }
 
-  fn checksum(&self) -> usize {
-    self.tape.iter().fold(0, |sum, val| sum + match val {
-      &Zero => 0, &One => 1
-    })
+  /// A machine from the TOML or JSON table format, starting in the first state
+  /// unless `initial_state` names another.
+  fn from_table(alphabet: Vec<String>, specs: Vec<(String, Vec<BranchSpec>)>, initial_state: Option<String>, max_steps: usize) -> Self {
+    let states = compile_states(specs, &alphabet);
+    let curr_state = match initial_state {
+      Some(name) => find_state(states.iter().map(|state| &state.name), &name),
+      None => 0,
+    };
+
+    TuringMachine::new(alphabet, states, curr_state, max_steps)
   }
-}
 
-fn main_1() {
-  let input = include_str!("../input/25");
-  let mut machine = TuringMachine::from(&input);
-  machine.run_to_max_steps();
+  /// A machine on a blank tape, with the blank symbol first in `alphabet`.
+  fn new(alphabet: Vec<String>, states: Vec<State>, curr_state: usize, max_steps: usize) -> Self {
+    let tape = Tape::new(alphabet.len());
+    // start in the middle, so short runs never have to grow the tape
+    let cursor = tape.len() / 2;
 
-  let checksum = machine.checksum();
-  println!("Checksum: {}", checksum);
-}
+    TuringMachine {
+      alphabet,
+      blank: 0,
+      tape,
+      cursor,
+      visited: (cursor, cursor),
+      origin: cursor,
+      start: curr_state,
+      curr_state,
+      max_steps,
+      curr_steps: 0,
+      halted: None,
+      states,
+    }
+  }
 
-pub fn main() {
-  main_1();
-}
+  /// Flattens the states into one table indexed by `state * symbols + symbol`,
+  /// with each transition's target already multiplied out.
+  fn transition_table(&self) -> Vec<Transition> {
+    let symbols = self.alphabet.len();
+    self.states.iter().flat_map(|state| state.branches.iter().map(|branch| Transition {
+      write: branch.write as u64,
+      right: branch.move_dir == Right,
+      next: branch.transition.map_or(HALT_ROW, |next| next * symbols),
+    })).collect()
+  }
 
-#[cfg(test)]
-mod tests {
-  use super::*;
+  /// The head's position relative to the cell it started on.
+  pub fn head_position(&self) -> isize {
+    self.cursor as isize - self.origin as isize
+  }
 
-  #[test]
-  fn parsing_source_works() {
-    let src = "Begin in state A.
-Perform a diagnostic checksum after 6 steps.
+  /// Runs the machine, capturing the tape before the first step and then after
+  /// every `every` steps, until there are `rows` rows or the machine halts.
+  pub fn space_time_diagram(&mut self, rows: usize, every: usize) -> SpaceTimeDiagram {
+    let mut diagram = SpaceTimeDiagram {
+      alphabet: self.alphabet.clone(),
+      state_names: self.states.iter().map(|state| state.name.clone()).collect(),
+      rows: Vec::with_capacity(rows),
+    };
 
-In state A:
-  If the current value is 0:
-    - Write the value 1.
-    - Move one slot to the right.
-    - Continue with state B.
-  If the current value is 1:
-    - Write the value 0.
-    - Move one slot to the left.
-    - Continue with state B.
+    while diagram.rows.len() < rows {
+      if !diagram.rows.is_empty() {
+        if self.halted.is_some() {
+          break;
+        }
+        self.run(every);
+      }
 
-In state B:
-  If the current value is 0:
-    - Write the value 1.
-    - Move one slot to the left.
-    - Continue with state A.
-  If the current value is 1:
-    - Write the value 1.
-    - Move one slot to the right.
-    - Continue with state A.
-";
-    let machine = TuringMachine::from(&src);
+      diagram.rows.push(DiagramRow {
+        step: self.curr_steps,
+        start: self.visited.0 as isize - self.origin as isize,
+        cells: self.visited_cells().map(|symbol| symbol as u8).collect(),
+        head: self.head_position(),
+        state: self.curr_state,
+      });
+    }
 
-    assert_eq!("A".to_string(), machine.curr_state);
-    assert_eq!(6, machine.max_steps);
-    assert_eq!(0, machine.curr_steps);
-    assert_eq!(0, machine.cursor);
+    diagram
+  }
 
-    assert_eq!(1, machine.tape.len());
-    assert_eq!(Some(&Zero), machine.tape.get(0));
+  fn visited_cells<'a>(&'a self) -> impl Iterator<Item=Symbol> + 'a {
+    let (leftmost, rightmost) = self.visited;
+    (leftmost..rightmost + 1).map(move |cell| self.tape.get(cell))
+  }
 
-    assert_eq!(2, machine.states.len());
-    assert_eq!(true, machine.states.contains_key("A"));
-    assert_eq!(true, machine.states.contains_key("B"));
+  /// Writes the transition table in the notation used for busy beavers: one
+  /// `_`-separated group per state, each with a write, move and next state per
+  /// symbol, e.g. `1RB1LB_1LA1RZ`. Symbols are written by alphabet index, and
+  /// `Z` stands for halting. The notation always starts in `A`, so the start
+  /// state is lettered `A` and the others follow from `B` in their order.
+  pub fn to_standard_notation(&self) -> String {
+    if self.alphabet.len() > 10 {
+      panic!("Standard notation only has digits for 10 symbols");
+    }
 
-    let state_a = machine.states.get("A").unwrap();
-    let a_zero = &state_a.zero_branch;
-    let a_one = &state_a.one_branch;
+    let order: Vec<usize> = Some(self.start).into_iter()
+      .chain((0..self.states.len()).filter(|&i| i != self.start))
+      .collect();
+    let mut letters = vec![0; order.len()];
+    for (letter, &i) in order.iter().enumerate() {
+      letters[i] = letter;
+    }
 
-    assert_eq!(One, a_zero.write);
-    assert_eq!(Right, a_zero.move_dir);
-    assert_eq!("B".to_string(), a_zero.transition);
+    let groups: Vec<String> = order.iter().map(|&i| &self.states[i]).map(|state| {
+      state.branches.iter().map(|branch| {
+        let dir = match branch.move_dir {
+          Left => 'L',
+          Right => 'R',
+        };
+        let next = match branch.transition {
+          Some(next) => state_letter(letters[next]),
+          None => "Z".to_string(),
+        };
+        format!("{}{}{}", branch.write, dir, next)
+      }).collect()
+    }).collect();
 
-    assert_eq!(Zero, a_one.write);
-    assert_eq!(Left, a_one.move_dir);
-    assert_eq!("B".to_string(), a_one.transition);
+    groups.join("_")
+  }
 
-    let state_b = machine.states.get("B").unwrap();
-    let b_zero = &state_b.zero_branch;
-    let b_one = &state_b.one_branch;
+  /// Writes the machine in the TOML table format read by `from_toml`, with
+  /// every name and symbol quoted.
+  pub fn to_toml(&self) -> String {
+    let mut spec = format!("start = {}\nsteps = {}\nblank = {}\n",
+      quote(&self.states[self.start].name), self.max_steps, quote(&self.alphabet[self.blank]));
 
-    assert_eq!(One, b_zero.write);
-    assert_eq!(Left, b_zero.move_dir);
-    assert_eq!("A".to_string(), b_zero.transition);
+    for state in &self.states {
+      spec.push_str(&format!("\n[states.{}]\n", quote(&state.name)));
+      for (read, branch) in self.alphabet.iter().zip(&state.branches) {
+        spec.push_str(&format!("{} = {}\n", quote(read), self.table_branch(branch)));
+      }
+    }
 
-    assert_eq!(One, b_one.write);
-    assert_eq!(Right, b_one.move_dir);
-    assert_eq!("A".to_string(), b_one.transition);
+    spec
   }
 
-  #[test]
-  fn running_a_turing_machine_works() {
-    let src = "Begin in state A.
-Perform a diagnostic checksum after 6 steps.
+  /// Writes the machine in the JSON form of the table format, as read by
+  /// `from_json`.
+  pub fn to_json(&self) -> String {
+    let states: Vec<String> = self.states.iter().map(|state| {
+      let branches: Vec<String> = self.alphabet.iter().zip(&state.branches)
+        .map(|(read, branch)| format!("      {}: {}", quote(read), self.table_branch(branch)))
+        .collect();
+      format!("    {}: {{\n{}\n    }}", quote(&state.name), branches.join(",\n"))
+    }).collect();
 
-In state A:
-  If the current value is 0:
-    - Write the value 1.
-    - Move one slot to the right.
-    - Continue with state B.
-  If the current value is 1:
-    - Write the value 0.
-    - Move one slot to the left.
-    - Continue with state B.
+    format!("{{\n  \"start\": {},\n  \"steps\": {},\n  \"blank\": {},\n  \"states\": {{\n{}\n  }}\n}}\n",
+      quote(&self.states[self.start].name), self.max_steps, quote(&self.alphabet[self.blank]), states.join(",\n"))
+  }
 
-In state B:
-  If the current value is 0:
-    - Write the value 1.
-    - Move one slot to the left.
-    - Continue with state A.
-  If the current value is 1:
-    - Write the value 1.
-    - Move one slot to the right.
-    - Continue with state A.
-";
-    let mut machine = TuringMachine::from(&src);
+  /// A branch as the `[write, move, next]` array of the table formats.
+  fn table_branch(&self, branch: &StateBranch) -> String {
+    let dir = match branch.move_dir {
+      Left => "L",
+      Right => "R",
+    };
+    let next = match branch.transition {
+      Some(next) => format!(", {}", quote(&self.states[next].name)),
+      None => String::new(),
+    };
+    format!("[{}, \"{}\"{}]", quote(&self.alphabet[branch.write]), dir, next)
+  }
+
+  /// Writes the machine in the puzzle's own format, as read by `from`. Halting
+  /// branches end with "Halt." in place of "Continue with state". A blank
+  /// symbol other than 0 gets a line of its own after the checksum line.
+  pub fn to_puzzle_spec(&self) -> String {
+    let mut spec = format!("Begin in state {}.\nPerform a diagnostic checksum after {} steps.\n",
+      self.states[self.start].name, self.max_steps);
+    if self.alphabet[self.blank] != "0" {
+      spec.push_str(&format!("{}{}.\n", BLANK_LINE, self.alphabet[self.blank]));
+    }
+
+    for state in &self.states {
+      spec.push_str(&format!("\nIn state {}:\n", state.name));
+      for (read, branch) in self.alphabet.iter().zip(&state.branches) {
+        let dir = match branch.move_dir {
+          Left => "left",
+          Right => "right",
+        };
+        spec.push_str(&format!("  If the current value is {}:\n", read));
+        spec.push_str(&format!("    - Write the value {}.\n", self.alphabet[branch.write]));
+        spec.push_str(&format!("    - Move one slot to the {}.\n", dir));
+        match branch.transition {
+          Some(next) => spec.push_str(&format!("    - Continue with state {}.\n", self.states[next].name)),
+          None => spec.push_str("    - Halt.\n"),
+        }
+      }
+    }
+
+    spec
+  }
+
+  /// How many visited cells hold each symbol, in alphabet order.
+  fn symbol_counts(&self) -> Vec<(&str, usize)> {
+    let mut counts = vec![0; self.alphabet.len()];
+    for val in self.visited_cells() {
+      counts[val] += 1;
+    }
+    self.alphabet.iter().map(String::as_str).zip(counts).collect()
+  }
+
+  /// Looks for a translated cycle within `budget` steps of the current
+  /// configuration. See `search_cycle` for what is detected, and what it costs.
+  pub fn find_translated_cycle(&self, budget: usize) -> Option<TranslatedCycle> {
+    self.search_cycle(budget).cycle.map(|cycle| TranslatedCycle {
+      start: self.curr_steps + cycle.start,
+      ..cycle
+    })
+  }
+
+  /// The checksum after `steps` more steps, if it can be found by simulating at
+  /// most `budget` of them: either `steps` is within budget, the machine halts
+  /// within budget, or it falls into a translated cycle which is then
+  /// extrapolated. `budget` is capped at `MAX_CYCLE_BUDGET`.
+  pub fn checksum_after(&self, steps: u128, budget: usize) -> Option<u128> {
+    let budget = budget.min(MAX_CYCLE_BUDGET);
+    let limit = if steps < budget as u128 { steps as usize } else { budget };
+    let CycleSearch { counts, cycle, halted } = self.search_cycle(limit);
+
+    if steps < counts.len() as u128 {
+      return Some(counts[steps as usize] as u128);
+    }
+    if halted {
+      return counts.last().map(|&count| count as u128);
+    }
+
+    cycle.map(|cycle| {
+      let periods = (steps - cycle.start as u128) / cycle.period as u128;
+      let phase = (steps - cycle.start as u128) % cycle.period as u128;
+      counts[cycle.start + phase as usize] as u128 + periods * cycle.growth as u128
+    })
+  }
+
+  /// Simulates up to `budget` steps on a scratch copy of the tape, stopping at
+  /// the first cycle found (with `start` relative to the current step) or when
+  /// the machine halts.
+  ///
+  /// Two kinds of cycle are detected. A translated cycle is found by comparing
+  /// the times the head reaches a new rightmost (or leftmost) cell: if two
+  /// such records are in the same state, and the tape the head covered
+  /// between them reads the same at both, the run between them repeats
+  /// forever, each time one record further along. A configuration that
+  /// repeats exactly (shift 0) is found with Brent's algorithm, by comparing
+  /// against checkpoints saved at power-of-two steps.
+  ///
+  /// Memory grows with the budget: a checksum and head position for every
+  /// step, plus the scratch tape and a checkpoint copy of it, which comes to
+  /// about 20 bytes a step. So `budget` is capped at `MAX_CYCLE_BUDGET`, and
+  /// records only keep the `RECORD_WINDOW` cells nearest the head, which
+  /// misses cycles that reach back further than that.
+  fn search_cycle(&self, budget: usize) -> CycleSearch {
+    let budget = budget.min(MAX_CYCLE_BUDGET);
+    let mut count = self.checksum();
+    let mut counts = vec![count];
+    if self.halted.is_some() {
+      return CycleSearch { counts, cycle: None, halted: true };
+    }
+
+    let (leftmost, rightmost) = self.visited;
+    let margin = budget + 1;
+
+    // the head moves at most `budget` cells, so the scratch tape never grows
+    let mut cells = vec![0u8; rightmost - leftmost + 1 + 2 * margin];
+    for cell in leftmost..rightmost + 1 {
+      cells[cell - leftmost + margin] = self.tape.get(cell) as u8;
+    }
+
+    let mut pos = self.cursor - leftmost + margin;
+    let (mut min_pos, mut max_pos) = (margin, rightmost - leftmost + margin);
+    let mut state = self.curr_state;
+
+    let mut positions = vec![pos];
+    let mut records: Vec<Vec<Record>> = (0..2 * self.states.len()).map(|_| Vec::new()).collect();
+    let mut checkpoint = Checkpoint { time: 0, state, pos, min_pos, max_pos, count, cells: cells.clone() };
+
+    for time in 1..budget + 1 {
+      let read = cells[pos] as Symbol;
+      let branch = &self.states[state].branches[read];
+
+      if read == self.blank && branch.write != self.blank {
+        count += 1;
+      }
+      else if read != self.blank && branch.write == self.blank {
+        count -= 1;
+      }
+      cells[pos] = branch.write as u8;
+
+      pos = match branch.move_dir {
+        Left => pos - 1,
+        Right => pos + 1,
+      };
+      counts.push(count);
+      positions.push(pos);
+
+      state = match branch.transition {
+        Some(next) => next,
+        None => return CycleSearch { counts, cycle: None, halted: true },
+      };
+
+      let side = if pos > max_pos {
+        max_pos = pos;
+        Some(Right)
+      }
+      else if pos < min_pos {
+        min_pos = pos;
+        Some(Left)
+      }
+      else {
+        None
+      };
+
+      let found = match side {
+        Some(side) => {
+          let (from, to) = if side == Right {
+            ((pos + 1).saturating_sub(RECORD_WINDOW).max(min_pos), pos)
+          }
+          else {
+            (pos, (pos + RECORD_WINDOW - 1).min(max_pos))
+          };
+          let clipped = from > min_pos || to < max_pos;
+          let record = Record { time, pos, start: from, cells: cells[from..to + 1].to_vec(), clipped };
+          let slot = 2 * state + if side == Right { 1 } else { 0 };
+
+          let found = find_record_match(&records[slot], &record, side, &positions, &counts);
+          if records[slot].len() == CYCLE_CANDIDATES {
+            records[slot].remove(0);
+          }
+          records[slot].push(record);
+          found
+        },
+        None => {
+          let c = &checkpoint;
+          let repeats = c.state == state && c.pos == pos && c.count == count
+            && c.min_pos == min_pos && c.max_pos == max_pos
+            && c.cells[min_pos..max_pos + 1] == cells[min_pos..max_pos + 1];
+
+          if repeats {
+            Some(TranslatedCycle { start: c.time, period: time - c.time, shift: 0, growth: 0 })
+          }
+          else {
+            None
+          }
+        },
+      };
+
+      if found.is_some() {
+        return CycleSearch { counts, cycle: found, halted: false };
+      }
+
+      if time.is_power_of_two() {
+        checkpoint = Checkpoint { time, state, pos, min_pos, max_pos, count, cells: cells.clone() };
+      }
+    }
+
+    CycleSearch { counts, cycle: None, halted: false }
+  }
+}
+
+/// How a `search_cycle` run ended, with the checksum after every step of it.
+struct CycleSearch {
+  counts: Vec<usize>,
+  cycle: Option<TranslatedCycle>,
+  halted: bool,
+}
+
+/// Maximum number of earlier records, per state and side, that a new record
+/// is compared against when searching for a translated cycle.
+const CYCLE_CANDIDATES: usize = 64;
+
+/// The most steps a cycle search simulates, whatever budget it is given.
+pub const MAX_CYCLE_BUDGET: usize = 1 << 22;
+
+/// How many cells back from the head a record keeps.
+const RECORD_WINDOW: usize = 1 << 12;
+
+/// A run that repeats every `period` steps from step `start` onwards, with the
+/// head `shift` cells further along the tape each time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TranslatedCycle {
+  pub start: usize,
+  pub period: usize,
+  /// Zero for a configuration that repeats exactly, negative for leftwards.
+  pub shift: isize,
+  /// How much the checksum grows each period.
+  pub growth: usize,
+}
+
+/// A time the head reached a new extreme cell, with the tape from that cell
+/// back to the other end of the visited region, or `RECORD_WINDOW` cells of
+/// it if that is nearer.
+struct Record {
+  time: usize,
+  pos: usize,
+  /// Scratch tape index of `cells[0]`.
+  start: usize,
+  cells: Vec<u8>,
+  /// Whether the visited region carries on past the cells kept.
+  clipped: bool,
+}
+
+impl Record {
+  fn cell(&self, pos: usize) -> u8 {
+    if pos < self.start || pos >= self.start + self.cells.len() {
+      0
+    }
+    else {
+      self.cells[pos - self.start]
+    }
+  }
+
+  /// Whether the cells from `from` to `to` are all known.
+  fn covers(&self, from: usize, to: usize) -> bool {
+    !self.clipped || (from >= self.start && to < self.start + self.cells.len())
+  }
+}
+
+struct Checkpoint {
+  time: usize,
+  state: usize,
+  pos: usize,
+  min_pos: usize,
+  max_pos: usize,
+  count: usize,
+  cells: Vec<u8>,
+}
+
+/// Compares a new record against earlier ones from the same state and side,
+/// newest first. The head's furthest excursion back from the record between
+/// the two times bounds the part of the tape that has to match.
+fn find_record_match(earlier: &[Record], record: &Record, side: Direction, positions: &[usize], counts: &[usize]) -> Option<TranslatedCycle> {
+  let mut scan = record.time;
+  let mut reach = record.pos;
+
+  for prev in earlier.iter().rev() {
+    while scan > prev.time {
+      scan -= 1;
+      reach = match side {
+        Right => reach.min(positions[scan]),
+        Left => reach.max(positions[scan]),
+      };
+    }
+
+    let matches = match side {
+      Right => {
+        let shift = record.pos - prev.pos;
+        prev.covers(reach, prev.pos) && record.covers(reach + shift, record.pos)
+          && (reach..prev.pos + 1).all(|pos| prev.cell(pos) == record.cell(pos + shift))
+      },
+      Left => {
+        let shift = prev.pos - record.pos;
+        prev.covers(prev.pos, reach) && record.covers(record.pos, reach - shift)
+          && (prev.pos..reach + 1).all(|pos| prev.cell(pos) == record.cell(pos - shift))
+      },
+    };
+
+    if matches {
+      return Some(TranslatedCycle {
+        start: prev.time,
+        period: record.time - prev.time,
+        shift: record.pos as isize - prev.pos as isize,
+        growth: counts[record.time] - counts[prev.time],
+      });
+    }
+  }
+
+  None
+}
+
+/// The base machine run inside a single block of a `MacroMachine`.
+#[derive(Debug, Copy, Clone)]
+struct BlockRun {
+  state: usize,
+  offset: usize,
+  block: u64,
+  steps: u128,
+  /// The side the head left the block by, or `None` if it is still inside.
+  exit: Option<Direction>,
+  halted: bool,
+}
+
+/// A `TuringMachine` simulated `k` cells at a time. Each block of `k` cells is
+/// packed into a `u64` and treated as one symbol, and what the machine does
+/// to a block between entering and leaving it is memoized. The tape either
+/// side of the head is stored as runs of identical blocks, so when the head
+/// sweeps through a run and leaves every block the same way in the same state,
+/// the whole run is crossed in a single step.
+pub struct MacroMachine<'a> {
+  machine: &'a TuringMachine,
+  k: usize,
+  cell_bits: usize,
+  state: usize,
+  offset: usize,
+  block: u64,
+  /// `(block, count)` runs, nearest the head last. Past the last run the tape is blank.
+  left: Vec<(u64, u128)>,
+  right: Vec<(u64, u128)>,
+  steps: u128,
+  halted: bool,
+  memo: HashMap<(usize, usize, u64), BlockRun>,
+}
+
+fn push_run(runs: &mut Vec<(u64, u128)>, block: u64, count: u128) {
+  if count == 0 {
+    return;
+  }
+  if let Some(top) = runs.last_mut() {
+    if top.0 == block {
+      top.1 += count;
+      return;
+    }
+  }
+  runs.push((block, count));
+}
+
+fn pop_run(runs: &mut Vec<(u64, u128)>) -> u64 {
+  match runs.pop() {
+    None => 0,
+    Some((block, count)) => {
+      if count > 1 {
+        runs.push((block, count - 1));
+      }
+      block
+    },
+  }
+}
+
+impl<'a> MacroMachine<'a> {
+  /// Starts from `machine`'s current configuration, with the head at the left
+  /// edge of its block.
+  pub fn new(machine: &'a TuringMachine, k: usize) -> Self {
+    let cell_bits = 1 << machine.tape.cell_shift;
+    if k == 0 || k * cell_bits > 64 {
+      panic!("Blocks of {} cells of {} bits don't fit in 64 bits", k, cell_bits);
+    }
+
+    let (leftmost, rightmost) = machine.visited;
+    let (leftmost, rightmost) = (leftmost as isize, rightmost as isize);
+    let block_at = |start: isize| (0..k).fold(0, |block, i| {
+      let cell = start + i as isize;
+      if cell < leftmost || cell > rightmost {
+        block
+      }
+      else {
+        block | (machine.tape.get(cell as usize) as u64) << (i * cell_bits)
+      }
+    });
+
+    let cursor = machine.cursor as isize;
+    let step = k as isize;
+
+    // blocks are collected nearest first, then pushed from the far end inwards
+    let mut left = Vec::new();
+    let mut blocks = Vec::new();
+    let mut start = cursor - step;
+    while start + step > leftmost {
+      blocks.push(block_at(start));
+      start -= step;
+    }
+    for &block in blocks.iter().rev() {
+      push_run(&mut left, block, 1);
+    }
+
+    let mut right = Vec::new();
+    let mut blocks = Vec::new();
+    let mut start = cursor + step;
+    while start <= rightmost {
+      blocks.push(block_at(start));
+      start += step;
+    }
+    for &block in blocks.iter().rev() {
+      push_run(&mut right, block, 1);
+    }
+
+    MacroMachine {
+      machine,
+      k,
+      cell_bits,
+      state: machine.curr_state,
+      offset: 0,
+      block: block_at(cursor),
+      left,
+      right,
+      steps: machine.curr_steps as u128,
+      halted: machine.halted.is_some(),
+      memo: HashMap::new(),
+    }
+  }
+
+  pub fn steps(&self) -> u128 {
+    self.steps
+  }
+
+  pub fn halted(&self) -> bool {
+    self.halted
+  }
+
+  /// Runs the base machine inside `block` until the head leaves it or
+  /// `limit` steps have passed. A configuration that repeats within the
+  /// block is a cycle the head will never leave, so it is skipped over.
+  fn run_in_block(&self, mut state: usize, mut offset: usize, mut block: u64, limit: u128) -> BlockRun {
+    let mask = (1u64 << self.cell_bits) - 1;
+    let mut steps = 0;
+    let mut seen = HashMap::new();
+
+    while steps < limit {
+      if let Some(earlier) = seen.insert((state, offset, block), steps) {
+        let period = steps - earlier;
+        steps += (limit - steps) / period * period;
+        seen.clear();
+        if steps == limit {
+          break;
+        }
+      }
+
+      let shift = offset * self.cell_bits;
+      let branch = &self.machine.states[state].branches[((block >> shift) & mask) as usize];
+      block = (block & !(mask << shift)) | (branch.write as u64) << shift;
+      steps += 1;
+
+      // where the head ends up after halting makes no difference to the tape
+      state = match branch.transition {
+        Some(next) => next,
+        None => return BlockRun { state, offset, block, steps, exit: None, halted: true },
+      };
+
+      match branch.move_dir {
+        Left if offset == 0 => return BlockRun { state, offset: self.k - 1, block, steps, exit: Some(Left), halted: false },
+        Right if offset == self.k - 1 => return BlockRun { state, offset: 0, block, steps, exit: Some(Right), halted: false },
+        Left => offset -= 1,
+        Right => offset += 1,
+      }
+    }
+
+    BlockRun { state, offset, block, steps, exit: None, halted: false }
+  }
+
+  fn transition(&mut self, state: usize, offset: usize, block: u64) -> BlockRun {
+    if let Some(run) = self.memo.get(&(state, offset, block)) {
+      return *run;
+    }
+    let run = self.run_in_block(state, offset, block, u128::MAX);
+    self.memo.insert((state, offset, block), run);
+    run
+  }
+
+  /// Runs the machine for up to `steps` more base machine steps, stopping
+  /// early if it halts.
+  pub fn run_steps(&mut self, steps: u128) {
+    let target = self.steps + steps;
+
+    while self.steps < target && !self.halted {
+      let (state, offset, block) = (self.state, self.offset, self.block);
+      let remaining = target - self.steps;
+      let mut run = self.transition(state, offset, block);
+
+      if run.steps > remaining || (run.exit.is_none() && !run.halted) {
+        // the head stays in this block until the target is reached
+        run = self.run_in_block(state, offset, block, remaining);
+      }
+
+      let dir = match run.exit {
+        Some(dir) => dir,
+        None => {
+          self.state = run.state;
+          self.offset = run.offset;
+          self.block = run.block;
+          self.steps += run.steps;
+          self.halted = run.halted;
+          break;
+        },
+      };
+
+      self.steps += run.steps;
+      self.state = run.state;
+      self.offset = run.offset;
+
+      let (behind, ahead) = match dir {
+        Left => (&mut self.right, &mut self.left),
+        Right => (&mut self.left, &mut self.right),
+      };
+      push_run(behind, run.block, 1);
+
+      // entering the next block from the same edge in the same state as this
+      // one: if it is also the same block, it will be left the same way too,
+      // as will the rest of its run
+      if run.state == state && run.offset == offset {
+        let (next, count) = match ahead.last() {
+          Some(&(next, count)) => (next, count),
+          None => (0, u128::MAX),
+        };
+
+        if next == block {
+          let repeats = count.min((target - self.steps) / run.steps);
+          let emptied = match ahead.last_mut() {
+            Some(top) => {
+              top.1 -= repeats;
+              top.1 == 0
+            },
+            None => false,
+          };
+          if emptied {
+            ahead.pop();
+          }
+          push_run(behind, run.block, repeats);
+          self.steps += repeats * run.steps;
+        }
+      }
+
+      self.block = pop_run(ahead);
+    }
+  }
+
+  fn nonblank_cells(&self, block: u64) -> u128 {
+    let mask = (1u64 << self.cell_bits) - 1;
+    (0..self.k).filter(|&i| (block >> (i * self.cell_bits)) & mask != 0).count() as u128
+  }
+
+  /// Counts the cells holding anything other than the blank symbol.
+  pub fn checksum(&self) -> u128 {
+    self.left.iter().chain(self.right.iter())
+      .map(|&(block, count)| count * self.nonblank_cells(block))
+      .sum::<u128>() + self.nonblank_cells(self.block)
+  }
+}
+
+impl Machine for TuringMachine {
+  fn max_steps(&self) -> usize {
+    self.max_steps
+  }
+
+  fn steps(&self) -> usize {
+    self.curr_steps
+  }
+
+  fn run(&mut self, steps: usize) -> Outcome {
+    if self.halted.is_some() {
+      return Outcome::Halted(self.curr_steps);
+    }
+
+    let symbols = self.alphabet.len();
+    let table = self.transition_table();
+
+    let cell_shift = self.tape.cell_shift;
+    let cells_per_word = 1 << self.tape.word_shift;
+    let mask = (1u64 << (1 << cell_shift)) - 1;
+
+    let mut row = self.curr_state * symbols;
+    let mut word_index = self.cursor >> self.tape.word_shift;
+    let mut slot = self.cursor & (cells_per_word - 1);
+    let (mut leftmost, mut rightmost) = self.visited;
+    let mut cursor = self.cursor;
+
+    // the word under the head is kept in a register and only written back when
+    // the head crosses into a neighbouring word
+    let mut word = self.tape.words[word_index];
+    let mut executed = 0;
+
+    while executed < steps {
+      let offset = slot << cell_shift;
+      let read = ((word >> offset) & mask) as usize;
+      let transition = &table[row + read];
+
+      // write the value to tape
+      word = (word & !(mask << offset)) | (transition.write << offset);
+
+      // move left or right, growing the tape if we run off either end
+      if transition.right {
+        cursor += 1;
+        slot += 1;
+        if slot == cells_per_word {
+          self.tape.words[word_index] = word;
+          slot = 0;
+          word_index += 1;
+          if word_index == self.tape.words.len() {
+            self.tape.grow_back();
+          }
+          word = self.tape.words[word_index];
+        }
+        if cursor > rightmost {
+          rightmost = cursor;
+        }
+      }
+      else {
+        if slot == 0 {
+          self.tape.words[word_index] = word;
+          if word_index == 0 {
+            let added = self.tape.grow_front();
+            word_index += added >> self.tape.word_shift;
+            cursor += added;
+            leftmost += added;
+            rightmost += added;
+            self.origin += added;
+          }
+          slot = cells_per_word;
+          word_index -= 1;
+          word = self.tape.words[word_index];
+        }
+        cursor -= 1;
+        slot -= 1;
+        if cursor < leftmost {
+          leftmost = cursor;
+        }
+      }
+
+      executed += 1;
+
+      // transition state, or stop in this one if the branch halts
+      if transition.next == HALT_ROW {
+        self.halted = Some((row / symbols, read));
+        break;
+      }
+      row = transition.next;
+    }
+
+    self.tape.words[word_index] = word;
+    self.cursor = cursor;
+    self.curr_state = row / symbols;
+    self.visited = (leftmost, rightmost);
+    self.curr_steps += executed;
+
+    match self.halted {
+      Some(_) => Outcome::Halted(self.curr_steps),
+      None => Outcome::StepLimit,
+    }
+  }
+
+  fn checksum(&self) -> usize {
+    if self.tape.cell_shift == 0 {
+      // one bit per cell, so the blank is the only zero bit
+      self.tape.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+    else {
+      self.visited_cells().filter(|&val| val != self.blank).count()
+    }
+  }
+}
+
+/// How a head moves after writing. Heads on a tape move left or right; a
+/// `Turmite`'s head moves on a grid, where up is towards negative `y`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Move {
+  Left, Right, Up, Down, Stay
+}
+
+/// A branch of a spec with a value read, written and a move for each head,
+/// before state names are resolved to indices.
+struct TupleBranchSpec {
+  read: Vec<Symbol>,
+  write: Vec<Symbol>,
+  moves: Vec<Move>,
+  transition: Option<String>,
+}
+
+struct TupleBranch {
+  write: Vec<Symbol>,
+  moves: Vec<Move>,
+  transition: Option<usize>,
+}
+
+struct TupleState {
+  name: String,
+  /// One branch per combination of symbols under the heads, indexed by
+  /// `tuple_index`.
+  branches: Vec<TupleBranch>,
+}
+
+/// The symbols under the heads as a single number, with the first head's
+/// symbol most significant.
+fn tuple_index(read: &[Symbol], symbols: usize) -> usize {
+  read.iter().fold(0, |index, &symbol| index * symbols + symbol)
+}
+
+/// The comma-separated values after the first of `keywords` in `line`, such
+/// as `0, 1` in "If the current values are 0, 1:".
+fn parse_list<'a>(line: &'a str, keywords: &[&str]) -> Vec<&'a str> {
+  let rest = keywords.iter()
+    .filter_map(|keyword| line.find(keyword).map(|i| &line[i + keyword.len()..]))
+    .next();
+  match rest {
+    Some(rest) => rest.split(',').map(|value| strip_period(value.trim())).collect(),
+    None => panic!("Expected one of {:?} in line: {}", keywords, line.trim()),
+  }
+}
+
+fn parse_move(raw: &str) -> Move {
+  match raw {
+    "left" => Move::Left,
+    "right" => Move::Right,
+    "up" => Move::Up,
+    "down" => Move::Down,
+    "stay" => Move::Stay,
+    _ => panic!("Got invalid move direction for a state, {}", raw),
+  }
+}
+
+/// Parses a state block where each branch may read and write several values
+/// and move several heads:
+///
+/// ```text
+///   If the current values are 0, 1:
+///     - Write the values 1, 1.
+///     - Move the heads right, stay.
+///     - Continue with state B.
+/// ```
+///
+/// The puzzle's own one-value wording is accepted too, as is "Move one slot
+/// up." and "down." for a head on a grid.
+fn parse_tuple_state_spec(spec: &[&str], alphabet: &mut Vec<String>) -> (String, Vec<TupleBranchSpec>) {
+  let mut iter = spec.iter();
+  let name_w = iter.next().unwrap().split_whitespace().nth(2).unwrap();
+  let name = strip_period(name_w).to_string();
+
+  let mut branches: Vec<TupleBranchSpec> = Vec::new();
+  while let Some(line) = iter.next() {
+    let read: Vec<Symbol> = parse_list(line, &["value is ", "values are "]).into_iter()
+      .map(|raw| intern_symbol(alphabet, raw))
+      .collect();
+    if branches.iter().any(|branch| branch.read == read) {
+      let values: Vec<&str> = read.iter().map(|&symbol| alphabet[symbol].as_str()).collect();
+      panic!("State {} has more than one branch for values {}", name, values.join(", "));
+    }
+
+    let write = parse_list(iter.next().unwrap(), &["value ", "values "]).into_iter()
+      .map(|raw| intern_symbol(alphabet, raw))
+      .collect();
+
+    let move_line = iter.next().unwrap();
+    let moves = if move_line.contains("Move one slot") {
+      vec![parse_move(strip_period(move_line.split_whitespace().last().unwrap()))]
+    }
+    else {
+      parse_list(move_line, &["heads "]).into_iter().map(parse_move).collect()
+    };
+
+    let transition_line = iter.next().unwrap();
+    let transition = if transition_line.trim() == "- Halt." {
+      None
+    }
+    else {
+      let transition_raw = transition_line.split_whitespace().nth(4).unwrap();
+      Some(strip_period(transition_raw).to_string())
+    };
+
+    branches.push(TupleBranchSpec { read, write, moves, transition });
+  }
+
+  (name, branches)
+}
+
+/// Resolves state names and places each state's branches by `tuple_index`,
+/// checking every branch has one value and move per head and only moves in
+/// the `allowed` directions.
+fn compile_tuple_states(specs: Vec<(String, Vec<TupleBranchSpec>)>, alphabet: &[String], heads: usize, allowed: &[Move]) -> Vec<TupleState> {
+  let indices = state_indices(&specs);
+  let symbols = alphabet.len();
+
+  specs.into_iter().map(|(name, spec)| {
+    let mut slots: Vec<Option<TupleBranch>> = (0..symbols.pow(heads as u32)).map(|_| None).collect();
+    for branch in spec {
+      if branch.read.len() != heads || branch.write.len() != heads || branch.moves.len() != heads {
+        panic!("State {} has a branch that doesn't have one value and move for each of {} heads", name, heads);
+      }
+      if let Some(dir) = branch.moves.iter().find(|dir| !allowed.contains(dir)) {
+        panic!("State {} moves a head {:?}, which this machine can't do", name, dir);
+      }
+
+      slots[tuple_index(&branch.read, symbols)] = Some(TupleBranch {
+        write: branch.write,
+        moves: branch.moves,
+        transition: resolve_transition(&indices, &name, branch.transition),
+      });
+    }
+
+    let branches = slots.into_iter().enumerate().map(|(index, slot)| match slot {
+      Some(branch) => branch,
+      None => {
+        let values: Vec<&str> = (0..heads).rev()
+          .map(|head| alphabet[index / symbols.pow(head as u32) % symbols].as_str())
+          .collect();
+        panic!("State {} has no branch for values {}", name, values.join(", "))
+      },
+    }).collect();
+
+    TupleState { name, branches }
+  }).collect()
+}
+
+/// A spec in the puzzle's format, extended as in `parse_tuple_state_spec`.
+/// Returns the alphabet, the states, the initial state and the step count.
+fn parse_tuple_spec(spec: &str, allowed: &[Move]) -> (Vec<String>, Vec<TupleState>, usize, usize) {
+  let mut lines = spec.lines();
+
+  let initial_state = parse_initial_state(&mut lines);
+  let max_steps = parse_max_steps(&mut lines);
+
+  // discard the blank line between preamble and first state
+  lines.next();
+  let mut alphabet = vec!["0".to_string()];
+  let specs = parse_states(&mut lines, &mut alphabet, parse_tuple_state_spec);
+  let heads = match specs.first().and_then(|(_, branches)| branches.first()) {
+    Some(branch) => branch.read.len(),
+    None => panic!("Spec has no states"),
+  };
+  let states = compile_tuple_states(specs, &alphabet, heads, allowed);
+
+  let curr_state = find_state(states.iter().map(|state| &state.name), &initial_state);
+  (alphabet, states, curr_state, max_steps)
+}
+
+/// A machine with several tapes, each with its own head. Every step reads the
+/// symbol under each head, then writes and moves each head independently.
+pub struct MultiTapeMachine {
+  alphabet: Vec<String>,
+  tapes: Vec<VecDeque<Symbol>>,
+  cursors: Vec<usize>,
+  curr_state: usize,
+  max_steps: usize,
+  curr_steps: usize,
+  halted: bool,
+  states: Vec<TupleState>,
+}
+
+impl MultiTapeMachine {
+  /// Parses a spec in the extended puzzle format. The number of tapes is the
+  /// number of values each branch reads, and heads move left, right or stay.
+  pub fn from(spec: &str) -> Self {
+    let (alphabet, states, curr_state, max_steps) = parse_tuple_spec(spec, &[Move::Left, Move::Right, Move::Stay]);
+    let tapes = states[0].branches[0].write.len();
+
+    MultiTapeMachine {
+      alphabet,
+      tapes: (0..tapes).map(|_| vec![0].into_iter().collect()).collect(),
+      cursors: vec![0; tapes],
+      curr_state,
+      max_steps,
+      curr_steps: 0,
+      halted: false,
+      states,
+    }
+  }
+
+  pub fn tapes(&self) -> usize {
+    self.tapes.len()
+  }
+
+  /// The symbols on one tape, from the leftmost cell visited to the rightmost.
+  pub fn tape(&self, tape: usize) -> Vec<&str> {
+    self.tapes[tape].iter().map(|&symbol| self.alphabet[symbol].as_str()).collect()
+  }
+}
+
+impl Machine for MultiTapeMachine {
+  fn max_steps(&self) -> usize {
+    self.max_steps
+  }
+
+  fn steps(&self) -> usize {
+    self.curr_steps
+  }
+
+  fn run(&mut self, steps: usize) -> Outcome {
+    let symbols = self.alphabet.len();
+
+    for _ in 0..steps {
+      if self.halted {
+        break;
+      }
+
+      let read = self.tapes.iter().zip(&self.cursors)
+        .fold(0, |index, (tape, &cursor)| index * symbols + tape[cursor]);
+      let branch = &self.states[self.curr_state].branches[read];
+
+      for ((tape, cursor), (&write, &dir)) in self.tapes.iter_mut().zip(&mut self.cursors).zip(branch.write.iter().zip(&branch.moves)) {
+        tape[*cursor] = write;
+        match dir {
+          Move::Left => if *cursor == 0 {
+            tape.push_front(0);
+          }
+          else {
+            *cursor -= 1;
+          },
+          Move::Right => {
+            *cursor += 1;
+            if *cursor == tape.len() {
+              tape.push_back(0);
+            }
+          },
+          _ => {},
+        }
+      }
+
+      self.curr_steps += 1;
+      match branch.transition {
+        Some(next) => self.curr_state = next,
+        None => self.halted = true,
+      }
+    }
+
+    if self.halted {
+      Outcome::Halted(self.curr_steps)
+    }
+    else {
+      Outcome::StepLimit
+    }
+  }
+
+  fn checksum(&self) -> usize {
+    self.tapes.iter().flat_map(|tape| tape.iter()).filter(|&&symbol| symbol != 0).count()
+  }
+}
+
+/// A two-dimensional Turing machine, or turmite: one head on an unbounded
+/// grid, moving up, down, left or right after each write. Only non-blank
+/// cells are stored.
+pub struct Turmite {
+  alphabet: Vec<String>,
+  grid: HashMap<(isize, isize), Symbol>,
+  head: (isize, isize),
+  curr_state: usize,
+  max_steps: usize,
+  curr_steps: usize,
+  halted: bool,
+  states: Vec<TupleState>,
+}
+
+impl Turmite {
+  /// Parses a spec in the extended puzzle format, with one value per branch.
+  pub fn from(spec: &str) -> Self {
+    let (alphabet, states, curr_state, max_steps) = parse_tuple_spec(spec, &[Move::Left, Move::Right, Move::Up, Move::Down, Move::Stay]);
+    if states[0].branches[0].write.len() != 1 {
+      panic!("A turmite has one head, so each branch reads one value");
+    }
+
+    Turmite {
+      alphabet,
+      grid: HashMap::new(),
+      head: (0, 0),
+      curr_state,
+      max_steps,
+      curr_steps: 0,
+      halted: false,
+      states,
+    }
+  }
+
+  /// The head's `(x, y)` position, starting from `(0, 0)`.
+  pub fn head(&self) -> (isize, isize) {
+    self.head
+  }
+
+  pub fn get(&self, x: isize, y: isize) -> &str {
+    &self.alphabet[self.grid.get(&(x, y)).cloned().unwrap_or(0)]
+  }
+}
+
+impl Machine for Turmite {
+  fn max_steps(&self) -> usize {
+    self.max_steps
+  }
+
+  fn steps(&self) -> usize {
+    self.curr_steps
+  }
+
+  fn run(&mut self, steps: usize) -> Outcome {
+    for _ in 0..steps {
+      if self.halted {
+        break;
+      }
+
+      let read = self.grid.get(&self.head).cloned().unwrap_or(0);
+      let branch = &self.states[self.curr_state].branches[read];
+
+      if branch.write[0] == 0 {
+        self.grid.remove(&self.head);
+      }
+      else {
+        self.grid.insert(self.head, branch.write[0]);
+      }
+      let (x, y) = self.head;
+      self.head = match branch.moves[0] {
+        Move::Left => (x - 1, y),
+        Move::Right => (x + 1, y),
+        Move::Up => (x, y - 1),
+        Move::Down => (x, y + 1),
+        Move::Stay => (x, y),
+      };
+
+      self.curr_steps += 1;
+      match branch.transition {
+        Some(next) => self.curr_state = next,
+        None => self.halted = true,
+      }
+    }
+
+    if self.halted {
+      Outcome::Halted(self.curr_steps)
+    }
+    else {
+      Outcome::StepLimit
+    }
+  }
+
+  fn checksum(&self) -> usize {
+    self.grid.len()
+  }
+}
+
+/// The tape after one step of a `SpaceTimeDiagram`, with positions relative to
+/// the cell the head started on.
+struct DiagramRow {
+  step: usize,
+  /// Position of `cells[0]`; cells outside `cells` are blank.
+  start: isize,
+  cells: Vec<u8>,
+  head: isize,
+  state: usize,
+}
+
+impl DiagramRow {
+  fn cell(&self, pos: isize) -> u8 {
+    let i = pos - self.start;
+    if i < 0 || i >= self.cells.len() as isize {
+      0
+    }
+    else {
+      self.cells[i as usize]
+    }
+  }
+}
+
+/// Cell colours for the blank symbol, the next symbol, and the rest in turn.
+const SYMBOL_COLORS: [[u8; 3]; 6] = [
+  [255, 255, 255], [32, 32, 32], [120, 120, 120], [190, 190, 190], [70, 50, 30], [150, 120, 90],
+];
+
+/// Head colours for each state in turn, with the matching ANSI background.
+const STATE_COLORS: [([u8; 3], u8); 6] = [
+  ([220, 50, 47], 41), ([133, 153, 0], 42), ([181, 137, 0], 43),
+  ([38, 139, 210], 44), ([211, 54, 130], 45), ([42, 161, 152], 46),
+];
+
+/// A run of a `TuringMachine`, one row per captured step, from top to bottom.
+pub struct SpaceTimeDiagram {
+  alphabet: Vec<String>,
+  state_names: Vec<String>,
+  rows: Vec<DiagramRow>,
+}
+
+impl SpaceTimeDiagram {
+  /// The leftmost and rightmost positions visited over the whole run.
+  fn extent(&self) -> (isize, isize) {
+    let left = self.rows.iter().map(|row| row.start).min().unwrap_or(0);
+    let right = self.rows.iter().map(|row| row.start + row.cells.len() as isize - 1).max().unwrap_or(0);
+    (left, right)
+  }
+
+  /// Writes the diagram as a binary PPM image, covering every cell the head
+  /// visited, with each cell drawn as a `scale` by `scale` square. The head's
+  /// cell is drawn in its state's colour.
+  pub fn write_ppm<W: Write>(&self, out: &mut W, scale: usize) -> io::Result<()> {
+    let (left, right) = self.extent();
+    let width = (right - left + 1) as usize;
+
+    common::write_ppm(out, width, self.rows.len(), scale, |x, y| {
+      let (row, pos) = (&self.rows[y], left + x as isize);
+      if pos == row.head {
+        STATE_COLORS[row.state % STATE_COLORS.len()].0
+      }
+      else {
+        SYMBOL_COLORS[row.cell(pos) as usize % SYMBOL_COLORS.len()]
+      }
+    })
+  }
+
+  /// Renders the diagram for a terminal, `width` cells across. Each row starts
+  /// with its step and state; the head's cell has its state's background
+  /// colour. The window scrolls to keep the head out of its outer quarters.
+  pub fn to_ansi(&self, width: usize) -> String {
+    let margin = width as isize / 4;
+    let step_width = self.rows.last().map_or(1, |row| row.step.to_string().len());
+    let name_width = self.state_names.iter().map(String::len).max().unwrap_or(1);
+
+    let mut window = self.rows.first().map_or(0, |row| row.head - width as isize / 2);
+    let mut out = String::new();
+
+    for row in &self.rows {
+      if row.head < window + margin {
+        window = row.head - margin;
+      }
+      else if row.head >= window + width as isize - margin {
+        window = row.head - width as isize + margin + 1;
+      }
+
+      out.push_str(&format!("{:>sw$} {:<nw$} |", row.step, self.state_names[row.state], sw = step_width, nw = name_width));
+      for pos in window..window + width as isize {
+        let symbol = row.cell(pos) as usize;
+        let glyph = if symbol == 0 { '.' } else { self.alphabet[symbol].chars().next().unwrap_or('?') };
+
+        if pos == row.head {
+          out.push_str(&format!("\x1b[{};97m{}\x1b[0m", STATE_COLORS[row.state % STATE_COLORS.len()].1, glyph));
+        }
+        else {
+          out.push(glyph);
+        }
+      }
+      out.push_str("|\n");
+    }
+
+    out
+  }
+}
+
+/// A 2-symbol machine whose transitions are filled in as the enumeration
+/// reaches them; `None` is a transition no run has needed yet.
+type PartialTable = Vec<[Option<(Symbol, Direction, usize)>; 2]>;
+
+/// Tallies from `enumerate_busy_beavers`, with champions in standard notation.
+#[derive(Debug, Default)]
+pub struct BusyBeaverReport {
+  pub machines: usize,
+  pub halted: usize,
+  pub step_limited: usize,
+  /// The halting machine that ran the most steps, and how many it ran.
+  pub most_steps: Option<(usize, String)>,
+  /// The halting machine that left the most ones on the tape, and how many.
+  pub most_ones: Option<(usize, String)>,
+}
+
+/// Builds a machine from a partial table, with every undefined transition
+/// writing a 1, moving right and halting.
+fn busy_beaver_machine(table: &PartialTable, budget: usize) -> TuringMachine {
+  let states = table.iter().enumerate().map(|(i, row)| State {
+    name: state_letter(i),
+    branches: row.iter().map(|transition| match *transition {
+      Some((write, move_dir, next)) => StateBranch { write, move_dir, transition: Some(next) },
+      None => StateBranch { write: 1, move_dir: Right, transition: None },
+    }).collect(),
+  }).collect();
+
+  TuringMachine::new(vec!["0".to_string(), "1".to_string()], states, 0, budget)
+}
+
+/// Runs every `states`-state, 2-symbol machine for up to `budget` steps and
+/// reports the halting champions.
+///
+/// Machines are enumerated in tree normal form: each starts with all
+/// transitions undefined (halting), and when a run halts on an undefined
+/// transition, that transition alone is filled in every possible way and the
+/// children are run in turn. Transitions no run reaches are never enumerated,
+/// and two symmetries are pruned: a transition may only continue to states
+/// already used plus the first unused one, since the rest differ only in
+/// naming, and the first move is fixed as `1RB`, since a machine moving left
+/// first mirrors one moving right.
+pub fn enumerate_busy_beavers(states: usize, budget: usize) -> BusyBeaverReport {
+  let mut report = BusyBeaverReport::default();
+
+  let mut root: PartialTable = vec![[None, None]; states];
+  if states > 1 {
+    root[0][0] = Some((1, Right, 1));
+  }
+  let mut stack = vec![root];
+
+  while let Some(table) = stack.pop() {
+    let mut machine = busy_beaver_machine(&table, budget);
+    report.machines += 1;
+
+    let steps = match machine.run(budget) {
+      Outcome::Halted(steps) => steps,
+      Outcome::StepLimit => {
+        report.step_limited += 1;
+        continue;
+      },
+    };
+    report.halted += 1;
+
+    let ones = machine.checksum();
+    if report.most_steps.as_ref().map_or(true, |&(best, _)| steps > best) {
+      report.most_steps = Some((steps, machine.to_standard_notation()));
+    }
+    if report.most_ones.as_ref().map_or(true, |&(best, _)| ones > best) {
+      report.most_ones = Some((ones, machine.to_standard_notation()));
+    }
+
+    // a machine with every transition defined can't halt, so one is kept back
+    let defined: Vec<(Symbol, Direction, usize)> = table.iter()
+      .flat_map(|row| row.iter().filter_map(|&transition| transition))
+      .collect();
+    if defined.len() + 1 == 2 * states {
+      continue;
+    }
+
+    let (state, symbol) = machine.halted.unwrap();
+    let highest = defined.iter().map(|&(_, _, next)| next).fold(state, usize::max);
+
+    for next in 0..(highest + 2).min(states) {
+      for &write in &[0, 1] {
+        for &move_dir in &[Left, Right] {
+          let mut child = table.clone();
+          child[state][symbol] = Some((write, move_dir, next));
+          stack.push(child);
+        }
+      }
+    }
+  }
+
+  report
+}
+
+fn main_1() {
+  let input = include_str!("../input/25");
+  let mut machine = TuringMachine::from(input);
+  machine.run_to_max_steps();
+
+  let checksum = machine.checksum();
+  println!("Checksum: {}", checksum);
+  println!("Symbol counts: {:?}", machine.symbol_counts());
+}
+
+pub fn main() {
+  main_1();
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parsing_source_works() {
+    let src = "Begin in state A.
+Perform a diagnostic checksum after 6 steps.
+
+In state A:
+  If the current value is 0:
+    - Write the value 1.
+    - Move one slot to the right.
+    - Continue with state B.
+  If the current value is 1:
+    - Write the value 0.
+    - Move one slot to the left.
+    - Continue with state B.
+
+In state B:
+  If the current value is 0:
+    - Write the value 1.
+    - Move one slot to the left.
+    - Continue with state A.
+  If the current value is 1:
+    - Write the value 1.
+    - Move one slot to the right.
+    - Continue with state A.
+";
+    let machine = TuringMachine::from(src);
+
+    assert_eq!(0, machine.curr_state);
+    assert_eq!(6, machine.max_steps);
+    assert_eq!(0, machine.curr_steps);
+    assert_eq!(machine.tape.len() / 2, machine.cursor);
+
+    assert_eq!(0, machine.tape.get(machine.cursor));
+    assert_eq!(vec!["0".to_string(), "1".to_string()], machine.alphabet);
+
+    assert_eq!(2, machine.states.len());
+    assert_eq!("A", machine.states[0].name);
+    assert_eq!("B", machine.states[1].name);
+
+    let state_a = &machine.states[0];
+    let a_zero = &state_a.branches[0];
+    let a_one = &state_a.branches[1];
+
+    assert_eq!(1, a_zero.write);
+    assert_eq!(Right, a_zero.move_dir);
+    assert_eq!(Some(1), a_zero.transition);
+
+    assert_eq!(0, a_one.write);
+    assert_eq!(Left, a_one.move_dir);
+    assert_eq!(Some(1), a_one.transition);
+
+    let state_b = &machine.states[1];
+    let b_zero = &state_b.branches[0];
+    let b_one = &state_b.branches[1];
+
+    assert_eq!(1, b_zero.write);
+    assert_eq!(Left, b_zero.move_dir);
+    assert_eq!(Some(0), b_zero.transition);
+
+    assert_eq!(1, b_one.write);
+    assert_eq!(Right, b_one.move_dir);
+    assert_eq!(Some(0), b_one.transition);
+  }
+
+  #[test]
+  fn running_a_turing_machine_works() {
+    let src = "Begin in state A.
+Perform a diagnostic checksum after 6 steps.
+
+In state A:
+  If the current value is 0:
+    - Write the value 1.
+    - Move one slot to the right.
+    - Continue with state B.
+  If the current value is 1:
+    - Write the value 0.
+    - Move one slot to the left.
+    - Continue with state B.
+
+In state B:
+  If the current value is 0:
+    - Write the value 1.
+    - Move one slot to the left.
+    - Continue with state A.
+  If the current value is 1:
+    - Write the value 1.
+    - Move one slot to the right.
+    - Continue with state A.
+";
+    let mut machine = TuringMachine::from(src);
+    machine.run_to_max_steps();
+
+    assert_eq!(3, machine.checksum());
+  }
+
+  fn branch(read: &str, write: &str, dir: &str, next: &str) -> String {
+    format!("  If the current value is {}:
+    - Write the value {}.
+    - Move one slot to the {}.
+    - Continue with state {}.
+", read, write, dir, next)
+  }
+
+  #[test]
+  fn running_a_three_symbol_machine_works() {
+    let src = format!("Begin in state A.
+Perform a diagnostic checksum after 20 steps.
+
+In state A:
+{}{}{}
+In state B:
+{}{}{}",
+      branch("0", "1", "right", "B"), branch("1", "2", "left", "B"), branch("2", "1", "left", "A"),
+      branch("0", "2", "left", "A"), branch("2", "1", "right", "B"), branch("1", "2", "right", "B"));
+    let mut machine = TuringMachine::from(&src);
+
+    assert_eq!(vec!["0", "1", "2"], machine.alphabet);
+    assert_eq!(3, machine.states[1].branches.len());
+    assert_eq!(1, machine.states[1].branches[2].write);
+
+    machine.run_to_max_steps();
+
+    assert_eq!(7, machine.checksum());
+    let counts = machine.symbol_counts();
+    assert_eq!(("1", 3), counts[1]);
+    assert_eq!(("2", 4), counts[2]);
+  }
+
+  #[test]
+  fn blank_symbol_is_configurable() {
+    let src = format!("Begin in state A.
+Perform a diagnostic checksum after 6 steps.
+
+In state A:
+{}{}
+In state B:
+{}{}",
+      branch("_", "#", "right", "B"), branch("#", "_", "left", "B"),
+      branch("_", "#", "left", "A"), branch("#", "#", "right", "A"));
+    let mut machine = TuringMachine::with_blank(&src, "_");
+    machine.run_to_max_steps();
+
+    assert_eq!(vec!["_", "#"], machine.alphabet);
+    assert_eq!(3, machine.checksum());
+  }
+
+  #[test]
+  #[should_panic(expected = "State B has no branch for value 1")]
+  fn missing_branch_is_rejected() {
+    let src = format!("Begin in state A.
+Perform a diagnostic checksum after 6 steps.
+
+In state A:
+{}{}
+In state B:
+{}",
+      branch("0", "1", "right", "B"), branch("1", "0", "left", "B"),
+      branch("0", "1", "left", "A"));
+    TuringMachine::from(&src);
+  }
+
+  #[test]
+  fn tape_packs_multi_bit_cells() {
+    let mut tape = Tape::new(3);
+    assert_eq!(32 * TAPE_CHUNK_WORDS, tape.len());
+
+    tape.words[0] = 2 << 62;
+    tape.words[1] = 1 | (2 << 2);
+
+    assert_eq!(0, tape.get(30));
+    assert_eq!(2, tape.get(31));
+    assert_eq!(1, tape.get(32));
+    assert_eq!(2, tape.get(33));
+    assert_eq!(0, tape.get(34));
+  }
+
+  #[test]
+  fn tape_grows_at_both_ends() {
+    let src = format!("Begin in state A.
+Perform a diagnostic checksum after 100000 steps.
+
+In state A:
+{}{}
+In state B:
+{}{}",
+      branch("0", "1", "left", "A"), branch("1", "1", "right", "B"),
+      branch("0", "1", "right", "B"), branch("1", "1", "right", "B"));
+
+    let mut machine = TuringMachine::from(&src);
     machine.run_to_max_steps();
+    assert_eq!(100000, machine.checksum());
+    // the head ends on the one blank cell it has visited
+    assert_eq!(("0", 1), machine.symbol_counts()[0]);
+
+    // walk back across everything written so far and off the right end
+    machine.curr_state = 1;
+    machine.run(300000);
+    assert_eq!(300000, machine.checksum());
+    assert_eq!(("1", 300000), machine.symbol_counts()[1]);
+  }
+
+  fn checksum_after_running(notation: &str, steps: usize) -> usize {
+    let mut machine = TuringMachine::from_standard_notation(notation);
+    machine.run(steps);
+    machine.checksum()
+  }
+
+  const TRANSLATED: &str = "1LC0RB_1LB1LA_1RC0RB";
+  const COUNTER: &str = "1LB1LC_0LC0LA_1RC0RA";
+
+  #[test]
+  fn translated_cycle_is_found() {
+    let src = TRANSLATED;
+    let cycle = TuringMachine::from_standard_notation(src).find_translated_cycle(10000).unwrap();
+    assert!(cycle.shift != 0);
+
+    // every period from the start on adds the same amount to the checksum
+    for &time in &[cycle.start, cycle.start + 17, cycle.start + 3 * cycle.period] {
+      let before = checksum_after_running(src, time);
+      let after = checksum_after_running(src, time + cycle.period);
+      assert_eq!(cycle.growth, after - before);
+    }
+
+    // a cycle found part way through a run is reported in absolute steps
+    let mut machine = TuringMachine::from_standard_notation(src);
+    machine.run(cycle.start + 5);
+    let later = machine.find_translated_cycle(10000).unwrap();
+    assert!(later.start >= cycle.start + 5);
+    assert_eq!(0, later.period % cycle.period);
+
+    // and still found once the tape is far wider than a record keeps
+    machine.run(200000);
+    assert!(machine.visited.1 - machine.visited.0 > 4 * RECORD_WINDOW);
+    let wide = machine.find_translated_cycle(10000).unwrap();
+    assert_eq!(0, wide.period % cycle.period);
+  }
+
+  #[test]
+  fn checksum_after_extrapolates_cycles() {
+    let src = TRANSLATED;
+    let machine = TuringMachine::from_standard_notation(src);
+    for &steps in &[0, 1, 999, 50000, 123457] {
+      assert_eq!(Some(checksum_after_running(src, steps) as u128), machine.checksum_after(steps as u128, 5000));
+    }
+
+    let walker = TuringMachine::from_standard_notation("1RA1RA");
+    assert_eq!(Some(10u128.pow(30)), walker.checksum_after(10u128.pow(30), 100));
+    assert_eq!(Some(10u128.pow(30)), walker.checksum_after(10u128.pow(30), usize::MAX));
+
+    let bouncer = TuringMachine::from_standard_notation("1RB1RB_1LA1LA");
+    let cycle = bouncer.find_translated_cycle(100).unwrap();
+    assert_eq!(0, cycle.shift);
+    assert_eq!(Some(2), bouncer.checksum_after(10u128.pow(20), 100));
+  }
+
+  #[test]
+  fn checksum_after_gives_up_on_irregular_machines() {
+    let counter = TuringMachine::from_standard_notation(COUNTER);
+    assert_eq!(None, counter.find_translated_cycle(10000));
+    assert_eq!(None, counter.checksum_after(1000000, 10000));
+    assert_eq!(Some(checksum_after_running(COUNTER, 10000) as u128), counter.checksum_after(10000, 10000));
+  }
+
+  #[test]
+  fn macro_machine_matches_base_machine() {
+    for &src in &[TRANSLATED, COUNTER, "1RB1LB_1LA1RA"] {
+
+      for k in 1..9 {
+        let machine = TuringMachine::from_standard_notation(src);
+        let mut macro_machine = MacroMachine::new(&machine, k);
+        let mut steps = 0;
+        for &more in &[0, 1, 7, 100, 2500] {
+          macro_machine.run_steps(more as u128);
+          steps += more;
+          assert_eq!(checksum_after_running(src, steps) as u128, macro_machine.checksum());
+        }
+        assert_eq!(steps as u128, macro_machine.steps());
+      }
+
+      // starting part way through a run, with the head anywhere in the tape
+      let mut machine = TuringMachine::from_standard_notation(src);
+      machine.run(333);
+      for k in 1..9 {
+        let mut macro_machine = MacroMachine::new(&machine, k);
+        macro_machine.run_steps(1000);
+        assert_eq!(checksum_after_running(src, 1333) as u128, macro_machine.checksum());
+      }
+    }
+  }
+
+  #[test]
+  fn macro_machine_runs_astronomically_far() {
+    let walker = TuringMachine::from_standard_notation("1RB1LA_0RA0RA");
+    let mut macro_machine = MacroMachine::new(&walker, 4);
+    macro_machine.run_steps(10u128.pow(30) + 3);
+
+    // every two steps write one 1 and move two cells right
+    assert_eq!(10u128.pow(30) / 2 + 2, macro_machine.checksum());
+  }
+
+  #[test]
+  fn halting_branches_stop_the_machine() {
+    let src = format!("Begin in state A.
+Perform a diagnostic checksum after 100 steps.
+
+In state A:
+{}{}
+In state B:
+{}  If the current value is 1:
+    - Write the value 1.
+    - Move one slot to the right.
+    - Halt.
+", branch("0", "1", "right", "B"), branch("1", "1", "left", "B"), branch("0", "1", "left", "A"));
+
+    let mut machine = TuringMachine::from(&src);
+    assert_eq!(None, machine.states[1].branches[1].transition);
+    assert_eq!(Outcome::StepLimit, machine.run(3));
+    assert_eq!(Outcome::Halted(6), machine.run_to_max_steps());
+    assert_eq!(Some((1, 1)), machine.halted);
+    assert_eq!(4, machine.checksum());
+
+    // a halted machine stays halted
+    assert_eq!(Outcome::Halted(6), machine.run(10));
+    assert_eq!("1RB1LB_1LA1RZ", machine.to_standard_notation());
+
+    let machine = TuringMachine::from(&src);
+    assert_eq!(Some(4), machine.checksum_after(10u128.pow(20), 100));
+    assert_eq!(Some(3), machine.checksum_after(4, 100));
+
+    let mut macro_machine = MacroMachine::new(&machine, 3);
+    macro_machine.run_steps(10u128.pow(20));
+    assert!(macro_machine.halted());
+    assert_eq!(6, macro_machine.steps());
+    assert_eq!(4, macro_machine.checksum());
+  }
+
+  #[test]
+  fn busy_beaver_champions_are_found() {
+    let expected = [(1, 1), (6, 4), (21, 6)];
+    for (states, &(steps, ones)) in (1..4).zip(expected.iter()) {
+      let report = enumerate_busy_beavers(states, 100);
+      assert_eq!(report.machines, report.halted + report.step_limited);
+      assert_eq!(steps, report.most_steps.unwrap().0);
+      assert_eq!(ones, report.most_ones.unwrap().0);
+    }
+  }
+
+  #[test]
+  fn puzzle_spec_round_trips() {
+    let input = include_str!("../input/25");
+    let machine = TuringMachine::from(input);
+    assert_eq!(input.trim_end(), machine.to_puzzle_spec().trim_end());
+
+    let src = TuringMachine::from_standard_notation("1RB2LA1RA_2LA2RB1LZ").to_puzzle_spec();
+    assert!(src.contains("  If the current value is 2:\n    - Write the value 1.\n    - Move one slot to the left.\n    - Halt.\n"));
+    assert_eq!("1RB2LA1RA_2LA2RB1LZ", TuringMachine::from(&src).to_standard_notation());
+  }
+
+  #[test]
+  fn puzzle_spec_keeps_the_blank_symbol() {
+    let mut machine = TuringMachine::from_toml("start = \"B\"
+steps = 6
+blank = \"_\"
+
+[states.A]
+_ = [\"#\", \"R\", \"B\"]
+\"#\" = [\"_\", \"L\", \"B\"]
+
+[states.B]
+_ = [\"#\", \"L\", \"A\"]
+\"#\" = [\"#\", \"R\", \"A\"]
+");
+    let spec = machine.to_puzzle_spec();
+    let mut round_tripped = TuringMachine::from(&spec);
+
+    assert!(spec.contains("steps.\nThe tape starts out filled with the value _.\n\nIn state A:"));
+    assert_eq!(vec!["_", "#"], round_tripped.alphabet);
+    assert_eq!(spec, round_tripped.to_puzzle_spec());
+    assert_eq!(machine.run_to_max_steps(), round_tripped.run_to_max_steps());
+    assert_eq!(machine.checksum(), round_tripped.checksum());
+  }
+
+  #[test]
+  fn puzzle_spec_blank_does_not_depend_on_branch_order() {
+    let zero_first = "Begin in state A.
+Perform a diagnostic checksum after 6 steps.
+
+In state A:
+  If the current value is 0:
+    - Write the value 1.
+    - Move one slot to the right.
+    - Continue with state B.
+  If the current value is 1:
+    - Write the value 0.
+    - Move one slot to the left.
+    - Continue with state B.
+
+In state B:
+  If the current value is 0:
+    - Write the value 1.
+    - Move one slot to the left.
+    - Continue with state A.
+  If the current value is 1:
+    - Write the value 1.
+    - Move one slot to the right.
+    - Continue with state A.
+";
+    let one_first = zero_first.replace("  If the current value is 0:
+    - Write the value 1.
+    - Move one slot to the right.
+    - Continue with state B.
+  If the current value is 1:
+    - Write the value 0.
+    - Move one slot to the left.
+    - Continue with state B.", "  If the current value is 1:
+    - Write the value 0.
+    - Move one slot to the left.
+    - Continue with state B.
+  If the current value is 0:
+    - Write the value 1.
+    - Move one slot to the right.
+    - Continue with state B.");
+    assert!(one_first != zero_first);
 
+    let mut machine = TuringMachine::from(&one_first);
+    assert_eq!(vec!["0", "1"], machine.alphabet);
+    machine.run_to_max_steps();
     assert_eq!(3, machine.checksum());
+    assert_eq!(zero_first, TuringMachine::from(&one_first).to_puzzle_spec());
+  }
+
+  #[test]
+  fn standard_notation_round_trips() {
+    for &notation in &["1RB1LB_1LA1RZ", "1RB1LC_1RC1RZ_1LA0LB", "1RB2LA1RA_2LA2RB0RA"] {
+      assert_eq!(notation, TuringMachine::from_standard_notation(notation).to_standard_notation());
+    }
+
+    let machine = TuringMachine::from_standard_notation("1RB---_1LA1RB");
+    assert_eq!(vec!["0", "1"], machine.alphabet);
+    assert_eq!(None, machine.states[0].branches[1].transition);
+    assert_eq!("1RB1RZ_1LA1RB", machine.to_standard_notation());
+  }
+
+  #[test]
+  #[should_panic(expected = "Invalid next state in standard notation: C")]
+  fn standard_notation_rejects_unknown_states() {
+    TuringMachine::from_standard_notation("1RB1LC_1LA1RZ");
+  }
+
+  #[test]
+  fn toml_round_trips() {
+    let machine = TuringMachine::from_standard_notation("1RB1LB_1LA1RZ");
+    let toml = machine.to_toml();
+    assert_eq!("start = \"A\"
+steps = 0
+blank = \"0\"
+
+[states.\"A\"]
+\"0\" = [\"1\", \"R\", \"B\"]
+\"1\" = [\"1\", \"L\", \"B\"]
+
+[states.\"B\"]
+\"0\" = [\"1\", \"L\", \"A\"]
+\"1\" = [\"1\", \"R\"]
+", toml);
+    assert_eq!("1RB1LB_1LA1RZ", TuringMachine::from_toml(&toml).to_standard_notation());
+
+    let machine = TuringMachine::from(include_str!("../input/25"));
+    let round_tripped = TuringMachine::from_toml(&machine.to_toml());
+    assert_eq!(machine.to_puzzle_spec(), round_tripped.to_puzzle_spec());
+  }
+
+  /// A machine whose state names and symbols need quoting and escaping.
+  fn awkward_machine() -> TuringMachine {
+    let mut machine = TuringMachine::from_standard_notation("1RB1LB_1LA1RZ");
+    machine.states[0].name = "a, \"b\"]".to_string();
+    machine.states[1].name = "[c]\\d".to_string();
+    machine.alphabet = vec!["\"".to_string(), ",]".to_string()];
+    machine
+  }
+
+  #[test]
+  fn toml_escapes_names_and_symbols() {
+    let machine = awkward_machine();
+    let toml = machine.to_toml();
+    assert!(toml.contains("\n[states.\"a, \\\"b\\\"]\"]\n\"\\\"\" = [\",]\", \"R\", \"[c]\\\\d\"]\n"));
+
+    let round_tripped = TuringMachine::from_toml(&toml);
+    assert_eq!(machine.alphabet, round_tripped.alphabet);
+    assert_eq!(toml, round_tripped.to_toml());
+    assert_eq!(machine.to_puzzle_spec(), round_tripped.to_puzzle_spec());
+  }
+
+  #[test]
+  #[should_panic(expected = "Expected a quoted string: 'A'")]
+  fn toml_rejects_literal_strings() {
+    TuringMachine::from_toml("start = 'A'");
+  }
+
+  #[test]
+  #[should_panic(expected = "Unsupported TOML after the value")]
+  fn toml_rejects_text_after_a_value() {
+    TuringMachine::from_toml("[states.A]\n\"0\" = [\"1\", \"R\", \"A\"], \"1\"");
+  }
+
+  #[test]
+  #[should_panic(expected = "Unsupported escape in quoted string")]
+  fn toml_rejects_unsupported_escapes() {
+    TuringMachine::from_toml("start = \"A\\x\"");
+  }
+
+  #[test]
+  fn json_round_trips() {
+    let machine = TuringMachine::from_standard_notation("1RB1LB_1LA1RZ");
+    let json = machine.to_json();
+    assert_eq!("{
+  \"start\": \"A\",
+  \"steps\": 0,
+  \"blank\": \"0\",
+  \"states\": {
+    \"A\": {
+      \"0\": [\"1\", \"R\", \"B\"],
+      \"1\": [\"1\", \"L\", \"B\"]
+    },
+    \"B\": {
+      \"0\": [\"1\", \"L\", \"A\"],
+      \"1\": [\"1\", \"R\"]
+    }
+  }
+}
+", json);
+    assert_eq!("1RB1LB_1LA1RZ", TuringMachine::from_json(&json).to_standard_notation());
+
+    let machine = TuringMachine::from(include_str!("../input/25"));
+    let round_tripped = TuringMachine::from_json(&machine.to_json());
+    assert_eq!(machine.to_toml(), round_tripped.to_toml());
+
+    let machine = awkward_machine();
+    let round_tripped = TuringMachine::from_json(&machine.to_json());
+    assert_eq!(machine.to_json(), round_tripped.to_json());
+    assert_eq!(machine.to_toml(), round_tripped.to_toml());
+  }
+
+  #[test]
+  fn json_reads_hand_written_tables() {
+    let machine = TuringMachine::from_json(r##"{"states": {"A": {"#": ["_", "L", "B"], "_": ["#", "R", "B"]},
+      "B": {"_": ["#", "L", "A"], "#": ["#", "R", "A"]}}, "blank": "_", "start": "B", "steps": 6}"##);
+    assert_eq!(vec!["_", "#"], machine.alphabet);
+    assert_eq!(6, machine.max_steps());
+    assert_eq!("1LB1RB_1RA0LA", machine.to_standard_notation());
+  }
+
+  #[test]
+  #[should_panic(expected = "Unsupported JSON value: true")]
+  fn json_rejects_unsupported_values() {
+    TuringMachine::from_json(r#"{"start": "A", "steps": true}"#);
+  }
+
+  #[test]
+  fn toml_reads_hand_written_tables() {
+    let machine = TuringMachine::from_toml("# a two state machine
+steps = 6
+start = \"B\"
+blank = \"_\"
+
+[states.A]
+\"#\" = [\"_\", \"L\", \"B\"]
+_ = [\"#\", \"R\", \"B\"]
+
+[states.B]
+_ = [\"#\", \"L\", \"A\"]
+\"#\" = [\"#\", \"R\", \"A\"]
+");
+    assert_eq!(vec!["_", "#"], machine.alphabet);
+    assert_eq!(1, machine.start);
+    assert_eq!(6, machine.max_steps());
+    assert_eq!("1LB1RB_1RA0LA", machine.to_standard_notation());
+  }
+
+  #[test]
+  fn standard_notation_keeps_the_start_state() {
+    let mut machine = TuringMachine::from_toml("start = \"B\"
+steps = 11
+
+[states.A]
+0 = [\"1\", \"R\", \"B\"]
+1 = [\"1\", \"L\", \"C\"]
+
+[states.B]
+0 = [\"1\", \"L\", \"A\"]
+1 = [\"0\", \"R\", \"C\"]
+
+[states.C]
+0 = [\"1\", \"R\", \"A\"]
+1 = [\"1\", \"L\"]
+");
+    let notation = machine.to_standard_notation();
+    assert_eq!("1LB0RC_1RA1LC_1RB1LZ", notation);
+
+    let mut round_tripped = TuringMachine::from_standard_notation(&notation);
+    assert_eq!(notation, round_tripped.to_standard_notation());
+    for _ in 0..machine.max_steps() {
+      machine.run(1);
+      round_tripped.run(1);
+      assert_eq!((machine.checksum(), machine.head_position()), (round_tripped.checksum(), round_tripped.head_position()));
+    }
+  }
+
+  #[test]
+  fn space_time_diagram_captures_every_kth_step() {
+    let mut machine = TuringMachine::from_standard_notation("1RB1LB_1LA1RZ");
+    let diagram = machine.space_time_diagram(10, 2);
+
+    let steps: Vec<usize> = diagram.rows.iter().map(|row| row.step).collect();
+    assert_eq!(vec![0, 2, 4, 6], steps);
+    assert_eq!(vec![0, 0, -2, 0], diagram.rows.iter().map(|row| row.head).collect::<Vec<_>>());
+    assert_eq!(vec![1, 1, 1, 1], diagram.rows[3].cells);
+    assert_eq!(-2, diagram.rows[3].start);
+  }
+
+  #[test]
+  fn space_time_diagram_writes_ppm() {
+    let mut machine = TuringMachine::from_standard_notation("1RB1LB_1LA1RZ");
+    let diagram = machine.space_time_diagram(4, 1);
+
+    let mut ppm = vec![];
+    diagram.write_ppm(&mut ppm, 2).unwrap();
+    let header = b"P6\n6 8\n255\n";
+    assert_eq!(&header[..], &ppm[..header.len()]);
+    assert_eq!(header.len() + 6 * 8 * 3, ppm.len());
+
+    // The head starts in state A on the middle cell of the top row.
+    let pixel = |x: usize, y: usize| &ppm[header.len() + (y * 6 + x) * 3..][..3];
+    assert_eq!(&STATE_COLORS[0].0[..], pixel(2, 0));
+    assert_eq!(&SYMBOL_COLORS[0][..], pixel(0, 0));
+    assert_eq!(&SYMBOL_COLORS[1][..], pixel(2, 2));
+  }
+
+  #[test]
+  fn ansi_diagram_follows_the_head() {
+    let mut machine = TuringMachine::from_standard_notation("1RA---");
+    let diagram = machine.space_time_diagram(9, 1);
+    let plain: Vec<String> = diagram.to_ansi(8).lines()
+      .map(|line| line.replace("\x1b[41;97m", "").replace("\x1b[42;97m", "").replace("\x1b[0m", ""))
+      .collect();
+
+    assert_eq!("0 A |........|", plain[0]);
+    assert_eq!("1 A |....1...|", plain[1]);
+    assert_eq!("2 A |...11...|", plain[2]);
+    assert_eq!("4 A |.1111...|", plain[4]);
+    assert_eq!("8 A |11111...|", plain[8]);
+  }
+
+  const LANGTONS_ANT: &str = "Begin in state N.
+Perform a diagnostic checksum after 11000 steps.
+
+In state N:
+  If the current value is 0:
+    - Write the value 1.
+    - Move one slot to the right.
+    - Continue with state E.
+  If the current value is 1:
+    - Write the value 0.
+    - Move one slot to the left.
+    - Continue with state W.
+
+In state E:
+  If the current value is 0:
+    - Write the value 1.
+    - Move one slot down.
+    - Continue with state S.
+  If the current value is 1:
+    - Write the value 0.
+    - Move one slot up.
+    - Continue with state N.
+
+In state S:
+  If the current value is 0:
+    - Write the value 1.
+    - Move one slot to the left.
+    - Continue with state W.
+  If the current value is 1:
+    - Write the value 0.
+    - Move one slot to the right.
+    - Continue with state E.
+
+In state W:
+  If the current value is 0:
+    - Write the value 1.
+    - Move one slot up.
+    - Continue with state N.
+  If the current value is 1:
+    - Write the value 0.
+    - Move one slot down.
+    - Continue with state S.
+";
+
+  #[test]
+  fn turmite_runs_langtons_ant() {
+    let mut ant = Turmite::from(LANGTONS_ANT);
+    ant.run(4);
+    assert_eq!(4, ant.checksum());
+    assert_eq!((0, 0), ant.head());
+    ant.run(1);
+    assert_eq!(3, ant.checksum());
+    assert_eq!("0", ant.get(0, 0));
+
+    // by now the ant is building its highway, which repeats every 104 steps
+    ant.run_to_max_steps();
+    let (checksum, (x, y)) = (ant.checksum(), ant.head());
+    ant.run(104);
+    assert_eq!(12, ant.checksum() - checksum);
+    let (dx, dy) = (ant.head().0 - x, ant.head().1 - y);
+    assert_eq!((2, 2), (dx.abs(), dy.abs()));
+  }
+
+  #[test]
+  fn multi_tape_moves_heads_independently() {
+    let mut machine = MultiTapeMachine::from("Begin in state A.
+Perform a diagnostic checksum after 7 steps.
+
+In state A:
+  If the current values are 0, 0:
+    - Write the values 1, 1.
+    - Move the heads right, stay.
+    - Continue with state A.
+  If the current values are 0, 1:
+    - Write the values 1, 0.
+    - Move the heads right, stay.
+    - Continue with state A.
+  If the current values are 1, 0:
+    - Write the values 1, 0.
+    - Move the heads left, left.
+    - Halt.
+  If the current values are 1, 1:
+    - Write the values 1, 1.
+    - Move the heads left, right.
+    - Halt.
+");
+    assert_eq!(2, machine.tapes());
+    assert_eq!(Outcome::StepLimit, machine.run_to_max_steps());
+    assert_eq!(8, machine.checksum());
+    assert_eq!(vec!["1", "1", "1", "1", "1", "1", "1", "0"], machine.tape(0));
+    assert_eq!(vec!["1"], machine.tape(1));
+  }
+
+  #[test]
+  fn multi_tape_with_one_tape_matches_turing_machine() {
+    let spec = include_str!("../input/25");
+    let mut machine = TuringMachine::from(spec);
+    let mut single = MultiTapeMachine::from(spec);
+    assert_eq!(1, single.tapes());
+
+    for _ in 0..5 {
+      machine.run(20_000);
+      single.run(20_000);
+      assert_eq!(machine.checksum(), single.checksum());
+    }
+  }
+
+  #[test]
+  #[should_panic(expected = "State A moves a head Up, which this machine can't do")]
+  fn multi_tape_rejects_grid_moves() {
+    MultiTapeMachine::from("Begin in state A.
+Perform a diagnostic checksum after 1 steps.
+
+In state A:
+  If the current value is 0:
+    - Write the value 1.
+    - Move one slot up.
+    - Halt.
+");
   }
 }